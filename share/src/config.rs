@@ -3,22 +3,31 @@
 use std::{
     collections::HashSet,
     fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
     net::Ipv4Addr,
     path::{Path, PathBuf},
     process::exit,
+    str::FromStr,
 };
 
 use anyhow::{anyhow, Context, Result};
-use libp2p::PeerId;
-use rand::{distributions::Alphanumeric, Rng};
-use serde::{Deserialize, Serialize};
+use hkdf::Hkdf;
+use libp2p::{identity, multiaddr::Protocol, Multiaddr, PeerId};
+use rand::RngCore;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use sha2::Sha256;
+use tracing::warn;
 
 use crate::{
-    database::{peer::ScsPeer, Store},
-    item::Secret,
+    database::{peer::ScsPeer, provenance::to_hex, Store},
+    item::{parse_duration, parse_size, Expiry, Namespace, Secret},
     Cli, Mode,
 };
 
+const IDENTITY_INFO: &[u8] = b"scs-identity-v1";
+/// Seeds shorter than this are easy to guess, and so are the items sealed to them
+const MIN_SEED_LEN: usize = 32;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     secret: Option<Vec<Secret>>,
@@ -30,6 +39,253 @@ pub struct Config {
     whitelists: Option<HashSet<Ipv4Addr>>,
     blacklists: Option<HashSet<Ipv4Addr>>,
     seed: String,
+    compression: Option<bool>,
+    accept: Option<AcceptPolicy>,
+    limits: Option<Limits>,
+    keys: Option<Vec<String>>,
+    pull: Option<Vec<PullRule>>,
+    replay_window: Option<u64>,
+    mailbox: Option<Mailbox>,
+    transcript: Option<bool>,
+    interactive: Option<bool>,
+    directories: Option<Directories>,
+    on_collision: Option<CollisionPolicy>,
+    import: Option<Vec<Import>>,
+    project: Option<String>,
+    environment: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    expires_in: Option<u64>,
+    burn_after_reading: Option<bool>,
+    secret_history: Option<usize>,
+}
+
+/// Secret keys a peer may pull without the owner being asked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullRule {
+    /// Name of a saved peer, or a PeerId
+    pub peer: String,
+    /// Keys the peer may pull. `*` allows every key
+    pub keys: Vec<String>,
+}
+
+impl PullRule {
+    pub fn allows(&self, key: &str) -> bool {
+        self.keys
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == key)
+    }
+}
+
+/// Limits on what peers can send. Sizes are either bytes or strings such as "40 MB".
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_file_size: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_request_size: Option<u64>,
+    pub max_items: Option<usize>,
+    /// Messages accepted from a peer while `scs` runs
+    pub max_messages: Option<usize>,
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> std::result::Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(size)) => parse_size(&size).map(Some).map_err(de::Error::custom),
+    }
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> std::result::Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Duration {
+        Seconds(u64),
+        Text(String),
+    }
+
+    match Option::<Duration>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Duration::Seconds(seconds)) => Ok(Some(seconds)),
+        Some(Duration::Text(duration)) => parse_duration(&duration)
+            .map(Some)
+            .map_err(de::Error::custom),
+    }
+}
+
+/// A node keeping deliveries for recipients that are offline. Sizes are either bytes or strings
+/// such as "40 MB".
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Mailbox {
+    /// PeerId of the mailbox node, reached through the relay, or its full address
+    pub address: Option<String>,
+    /// Seconds deliveries are kept for
+    pub retention: Option<u64>,
+    /// Total size of the deliveries kept for one recipient, or left by one sender
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// Deliveries kept for one recipient, or left by one sender
+    pub max_deposits: Option<usize>,
+    /// Total size of the deliveries kept for everyone
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_total_size: Option<u64>,
+}
+
+impl Mailbox {
+    /// Defaults to a week.
+    pub fn retention(&self) -> u64 {
+        self.retention.unwrap_or(7 * 24 * 60 * 60)
+    }
+
+    /// Defaults to 100 MB.
+    pub fn max_size(&self) -> u64 {
+        self.max_size.unwrap_or(100_000_000)
+    }
+
+    pub fn max_deposits(&self) -> usize {
+        self.max_deposits.unwrap_or(100)
+    }
+
+    /// Defaults to 1 GB.
+    pub fn max_total_size(&self) -> u64 {
+        self.max_total_size.unwrap_or(1_000_000_000)
+    }
+
+    /// The PeerId of the mailbox node and the address to dial it at, which goes through
+    /// `relay` when only its PeerId is configured.
+    pub fn peer(&self, relay: &Multiaddr) -> Result<Option<(PeerId, Multiaddr)>> {
+        let address = match &self.address {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+
+        if let Ok(peer_id) = PeerId::from_str(address) {
+            let address = relay
+                .clone()
+                .with(Protocol::P2pCircuit)
+                .with(Protocol::P2p(peer_id));
+            return Ok(Some((peer_id, address)));
+        }
+
+        let address = Multiaddr::from_str(address)
+            .map_err(|err| anyhow!("Invalid mailbox address {address}: {err}"))?;
+        match address.iter().last() {
+            Some(Protocol::P2p(peer_id)) => Ok(Some((peer_id, address))),
+            _ => Err(anyhow!(
+                "The mailbox address {address} must end with the PeerId of the mailbox node"
+            )),
+        }
+    }
+}
+
+/// Which files of a directory passed as a file are sent. Patterns are globs, as in `.gitignore`,
+/// matched against the paths of the files within the directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Directories {
+    /// Only files matching one of these are sent
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    /// Whether files ignored by `.gitignore` files are left out
+    pub gitignore: Option<bool>,
+}
+
+impl Directories {
+    pub fn include(&self) -> Vec<String> {
+        self.include.clone().unwrap_or_default()
+    }
+
+    pub fn exclude(&self) -> Vec<String> {
+        self.exclude.clone().unwrap_or_default()
+    }
+
+    /// Defaults to true.
+    pub fn gitignore(&self) -> bool {
+        self.gitignore.unwrap_or(true)
+    }
+}
+
+/// A file secrets are read from when sending.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Import {
+    pub path: String,
+    /// Guessed from the name and contents of the file when left out
+    pub format: Option<ImportFormat>,
+    /// Only these keys are sent
+    pub only: Option<Vec<String>>,
+    pub except: Option<Vec<String>>,
+}
+
+impl Import {
+    pub fn new(path: &str) -> Import {
+        Import {
+            path: path.to_string(),
+            format: None,
+            only: None,
+            except: None,
+        }
+    }
+
+    /// Whether the secret `key` is sent.
+    pub fn selects(&self, key: &str) -> bool {
+        let only = self.only.as_ref();
+        let except = self.except.as_ref();
+        only.map_or(true, |only| only.iter().any(|selected| selected == key))
+            && !except.is_some_and(|except| except.iter().any(|excluded| excluded == key))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// `KEY=VALUE` lines
+    Dotenv,
+    /// A flat object
+    Json,
+    /// A flat map
+    Yaml,
+    /// A Kubernetes `Secret` manifest
+    Kubernetes,
+}
+
+/// How incoming items are approved before they are saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AcceptPolicy {
+    /// Ask for every delivery
+    #[default]
+    Prompt,
+    /// Accept deliveries from saved peers and ask for the rest
+    Saved,
+    /// Accept every delivery
+    All,
+}
+
+/// What is done with a file received under the name of one already saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    /// Replace the saved file
+    #[default]
+    Overwrite,
+    /// Save the new file as `name (1).ext`
+    Rename,
+    /// Keep the saved file and refuse the new one
+    Skip,
+    /// Keep the saved file as `name.~1~` and save the new one under its name
+    Version,
 }
 
 impl Config {
@@ -41,17 +297,72 @@ impl Config {
         Ok(PathBuf::from(path))
     }
 
-    fn from_cli(opts: &Cli) -> Result<Config> {
-        let secrets = match &opts.secret {
-            Some(scs) => {
-                let secrets = scs.iter().map(Secret::from).collect::<Vec<_>>();
-                Some(secrets)
+    /// Where the generated seed is kept: the configuration directory of `scs`, apart from the
+    /// items it receives, so a copy of them does not hold the key they are sealed to. On macOS the
+    /// configuration and data directories are the same, so it is kept with the preferences.
+    fn create_key_path() -> Result<PathBuf> {
+        let dirs =
+            directories_next::ProjectDirs::from("com", "onboardbase", "secureshare").unwrap();
+        #[cfg(target_os = "macos")]
+        let path = match dirs.config_dir().ancestors().nth(2) {
+            Some(library) => library.join("Preferences").join(dirs.project_path()),
+            None => return Err(anyhow!("Failed to find the preferences directory")),
+        };
+        #[cfg(not(target_os = "macos"))]
+        let path = dirs.config_dir().to_path_buf();
+        fs::create_dir_all(&path).context("Failed to create the configuration directory")?;
+        Ok(path)
+    }
+
+    /// 32 random bytes, in hex.
+    fn random_seed() -> String {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        to_hex(&bytes)
+    }
+
+    /// Items are stored sealed to our identity key, so receivers without a configured seed
+    /// keep a generated one in `dir` to be able to read what they received. Only the owner can
+    /// read the file.
+    fn load_or_create_seed(dir: &Path) -> Result<String> {
+        let path = dir.join("seed");
+        if let Ok(seed) = fs::read_to_string(&path) {
+            return Ok(seed.trim().to_string());
+        }
+
+        let seed = Config::random_seed();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(mut file) => {
+                file.write_all(seed.as_bytes())
+                    .context("Failed to save generated seed")?;
+                Ok(seed)
+            }
+            //another `scs` process created it in the meantime
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                Ok(fs::read_to_string(&path)?.trim().to_string())
             }
-            None => None,
+            Err(err) => Err(err).context("Failed to save generated seed"),
+        }
+    }
+
+    fn from_cli(opts: &Cli) -> Result<Config> {
+        //in the other modes, `-s` selects secrets by key
+        let secrets = match opts.mode {
+            Mode::Send => Secret::from_args(
+                opts.secret.as_deref().unwrap_or_default(),
+                opts.secret_file.as_deref().unwrap_or_default(),
+                opts.secret_env.as_deref().unwrap_or_default(),
+                opts.secret_stdin.as_deref(),
+            )?,
+            _ => vec![],
         };
 
         let config = Config {
-            secret: secrets,
+            secret: (!secrets.is_empty()).then_some(secrets),
             message: opts.message.clone(),
             file: opts.file.clone(),
             port: opts.port.unwrap_or(0),
@@ -59,11 +370,27 @@ impl Config {
             save_path: Config::create_default_path()?,
             whitelists: None,
             blacklists: None,
-            seed: rand::thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(32)
-                .map(char::from)
-                .collect(),
+            seed: match opts.mode {
+                Mode::Send => Config::random_seed(),
+                _ => Config::load_or_create_seed(&Config::create_key_path()?)?,
+            },
+            compression: None,
+            accept: None,
+            limits: None,
+            keys: None,
+            pull: None,
+            replay_window: None,
+            mailbox: None,
+            transcript: None,
+            interactive: None,
+            directories: None,
+            on_collision: None,
+            import: None,
+            project: opts.project.clone(),
+            environment: opts.env.clone(),
+            expires_in: None,
+            burn_after_reading: None,
+            secret_history: None,
         };
         Ok(config)
     }
@@ -85,6 +412,9 @@ impl Config {
             }
             config
         })?;
+        if config.seed.len() < MIN_SEED_LEN {
+            warn!("The seed is shorter than {MIN_SEED_LEN} characters. Anyone who guesses it can read the items sent to you");
+        }
 
         Ok(config)
     }
@@ -124,21 +454,79 @@ impl Config {
         }
 
         let rpm = match &opts.mode {
-            Mode::Send => Some(Self::remote_peer_id_polyfill(opts, store)?),
-            Mode::Receive => None,
+            Mode::Send | Mode::Request => Some(Self::remote_peer_id_polyfill(opts, store)?),
+            //without a peer, `chat` waits for one to join
+            Mode::Chat if opts.remote_peer_id.is_none() && opts.name.is_none() => None,
+            Mode::Chat => Some(Self::remote_peer_id_polyfill(opts, store)?),
+            Mode::Receive
+            | Mode::Mailbox
+            | Mode::Read
+            | Mode::Export
+            | Mode::History
+            | Mode::Rollback
+            | Mode::Verify
+            | Mode::Receipts => None,
             Mode::List => exit(1),
         };
 
-        match &opts.config {
-            None => {
-                let config = Config::from_cli(opts)?;
-                Ok((opts.mode, rpm, config))
-            }
-            Some(path) => {
-                let config = Config::from_config_file(path.to_string())?;
-                Ok((opts.mode, rpm, config))
-            }
+        let mut config = match &opts.config {
+            None => Config::from_cli(opts)?,
+            Some(path) => Config::from_config_file(path.to_string())?,
+        };
+        if opts.accept_all {
+            config.accept = Some(AcceptPolicy::All);
+        }
+        if opts.mode == Mode::Request && opts.secret.is_some() {
+            config.keys = opts.secret.clone();
+        }
+        if opts.transcript {
+            config.transcript = Some(true);
+        }
+        if opts.interactive {
+            config.interactive = Some(true);
+        }
+        if opts.include.is_some() || opts.exclude.is_some() {
+            let mut directories = config.directories();
+            let include = [
+                directories.include(),
+                opts.include.clone().unwrap_or_default(),
+            ];
+            let exclude = [
+                directories.exclude(),
+                opts.exclude.clone().unwrap_or_default(),
+            ];
+            directories.include = Some(include.concat());
+            directories.exclude = Some(exclude.concat());
+            config.directories = Some(directories);
+        }
+        if let Some(paths) = &opts.import {
+            let mut imports = config.imports();
+            imports.extend(paths.iter().map(|path| Import {
+                only: opts.only.clone(),
+                except: opts.except.clone(),
+                ..Import::new(path)
+            }));
+            config.import = Some(imports);
         }
+        if opts.project.is_some() {
+            config.project = opts.project.clone();
+        }
+        if opts.env.is_some() {
+            config.environment = opts.env.clone();
+        }
+        config.namespace().check()?;
+        if let Some(duration) = &opts.expires_in {
+            config.expires_in = Some(parse_duration(duration)?);
+        }
+        if opts.burn {
+            config.burn_after_reading = Some(true);
+        }
+        if let Some(address) = &opts.mailbox {
+            let mut mailbox = config.mailbox();
+            mailbox.address = Some(address.clone());
+            config.mailbox = Some(mailbox);
+        }
+        Ok((opts.mode, rpm, config))
     }
 
     pub fn verbose(&self) -> bool {
@@ -173,17 +561,99 @@ impl Config {
         self.blacklists.clone()
     }
 
-    fn pad_seed_key(&self, mut s: String) -> String {
-        while s.len() < 32 {
-            s.push(' ');
+    /// Whether the payloads sealed for a peer are compressed first. On unless turned off in the config file.
+    pub fn compression(&self) -> bool {
+        self.compression.unwrap_or(true)
+    }
+
+    pub fn accept(&self) -> AcceptPolicy {
+        self.accept.unwrap_or_default()
+    }
+
+    /// Secret keys to pull from the remote peer in `request` mode.
+    pub fn keys(&self) -> Option<Vec<String>> {
+        self.keys.clone()
+    }
+
+    pub fn pull_rules(&self) -> Vec<PullRule> {
+        self.pull.clone().unwrap_or_default()
+    }
+
+    /// How many seconds old a request may be before it is refused as a replay. Defaults to 10 minutes.
+    pub fn replay_window(&self) -> u64 {
+        self.replay_window.unwrap_or(10 * 60)
+    }
+
+    /// How many seconds old a delivery collected from a mailbox may be: as old as the mailbox
+    /// keeps them, and at least the replay window.
+    pub fn mailbox_window(&self) -> u64 {
+        self.replay_window().max(self.mailbox().retention())
+    }
+
+    /// Whether chats are saved. Defaults to false.
+    pub fn transcript(&self) -> bool {
+        self.transcript.unwrap_or(false)
+    }
+
+    /// Whether `send` keeps the connection open for more items typed at a prompt, and `receive`
+    /// reads cancellations typed at one. Defaults to false.
+    pub fn interactive(&self) -> bool {
+        self.interactive.unwrap_or(false)
+    }
+
+    pub fn mailbox(&self) -> Mailbox {
+        self.mailbox.clone().unwrap_or_default()
+    }
+
+    pub fn on_collision(&self) -> CollisionPolicy {
+        self.on_collision.unwrap_or_default()
+    }
+
+    pub fn directories(&self) -> Directories {
+        self.directories.clone().unwrap_or_default()
+    }
+
+    /// The project and environment secrets are sent in, unless they are given their own.
+    pub fn namespace(&self) -> Namespace {
+        Namespace {
+            project: self.project.clone(),
+            environment: self.environment.clone(),
         }
-        s.truncate(32);
-        s
     }
 
-    pub fn seed_key(&self) -> String {
-        let seed = self.seed.clone();
-        self.pad_seed_key(seed)
+    /// How long the secrets sent can be read for, unless they are given their own expiry.
+    pub fn expiry(&self) -> Option<Expiry> {
+        Expiry::new(self.expires_in, self.burn_after_reading.unwrap_or(false))
+    }
+
+    /// Earlier values kept of each received secret, 10 unless set.
+    pub fn secret_history(&self) -> usize {
+        self.secret_history.unwrap_or(10)
+    }
+
+    /// Files secrets are read from when sending.
+    pub fn imports(&self) -> Vec<Import> {
+        self.import.clone().unwrap_or_default()
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits.clone().unwrap_or_default()
+    }
+
+    /// The most a sealed item may hold once opened, so a small compressed payload can't fill our
+    /// memory: the maximum file size, or 1 GB if there is none.
+    pub fn max_opened_size(&self) -> u64 {
+        self.limits().max_file_size.unwrap_or(1_000_000_000)
+    }
+
+    /// The identity derived from the seed with HKDF. Items sent to us are sealed to this key and
+    /// saved as they are, so anyone who knows the seed can read what is in `save_path`.
+    pub fn keypair(&self) -> identity::Keypair {
+        let mut bytes = [0u8; 32];
+        Hkdf::<Sha256>::new(None, self.seed.as_bytes())
+            .expand(IDENTITY_INFO, &mut bytes)
+            .expect("32 bytes is a valid length");
+        identity::Keypair::ed25519_from_bytes(bytes).expect("only errors on wrong length")
     }
 }
 
@@ -193,10 +663,12 @@ mod tests {
 
     use crate::{database::Store, item::Secret, Cli, Mode};
 
-    use super::Config;
+    use super::{
+        AcceptPolicy, CollisionPolicy, Config, Directories, ImportFormat, Limits, Mailbox,
+    };
     use anyhow::{Ok, Result};
     use assert_fs::prelude::FileWriteStr;
-    use libp2p::PeerId;
+    use libp2p::{Multiaddr, PeerId};
 
     #[test]
     fn default_path_created() -> Result<()> {
@@ -220,6 +692,9 @@ mod tests {
 
         let opts = Cli {
             secret,
+            secret_file: None,
+            secret_env: None,
+            secret_stdin: None,
             message,
             file,
             mode,
@@ -228,6 +703,23 @@ mod tests {
             port,
             config,
             name,
+            accept_all: false,
+            mailbox: None,
+            transcript: false,
+            interactive: false,
+            include: None,
+            exclude: None,
+            import: None,
+            only: None,
+            except: None,
+            format: None,
+            output: None,
+            session: None,
+            project: None,
+            env: None,
+            expires_in: None,
+            burn: false,
+            to: None,
         };
         let db_path = assert_fs::NamedTempFile::new("scs_config.db3")?;
         let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
@@ -260,14 +752,30 @@ mod tests {
             whitelists: None,
             blacklists: None,
             seed: "test".to_string(),
+            compression: None,
+            accept: None,
+            limits: None,
+            keys: None,
+            pull: None,
+            replay_window: None,
+            mailbox: None,
+            transcript: None,
+            interactive: None,
+            directories: None,
+            on_collision: None,
+            import: None,
+            project: None,
+            environment: None,
+            expires_in: None,
+            burn_after_reading: None,
+            secret_history: None,
         };
         Ok(config)
     }
 
     #[test]
     fn config_file() -> Result<()> {
-        let yaml_config = format!(
-            "
+        let yaml_config = "
             port: 5555 
             save_path: 'default'
             secret:
@@ -281,7 +789,7 @@ mod tests {
             debug: 1
             seed: test
         "
-        );
+        .to_string();
         let file = assert_fs::NamedTempFile::new("config.yml")?;
         file.write_str(&yaml_config)?;
         let config = Config::from_config_file(file.path().to_str().unwrap().to_string())?;
@@ -313,6 +821,9 @@ mod tests {
 
         let opts = Cli {
             secret,
+            secret_file: None,
+            secret_env: None,
+            secret_stdin: None,
             message,
             file,
             mode,
@@ -321,6 +832,23 @@ mod tests {
             port,
             config,
             name,
+            accept_all: false,
+            mailbox: None,
+            transcript: false,
+            interactive: false,
+            include: None,
+            exclude: None,
+            import: None,
+            only: None,
+            except: None,
+            format: None,
+            output: None,
+            session: None,
+            project: None,
+            env: None,
+            expires_in: None,
+            burn: false,
+            to: None,
         };
 
         let db_path = assert_fs::NamedTempFile::new("scs_polyfill.db3")?;
@@ -390,51 +918,278 @@ mod tests {
     }
 
     #[test]
-    fn lists() -> Result<()> {
+    fn compression() -> Result<()> {
         let config = make_config()?;
-        assert_eq!(config.blacklists(), None);
-        assert_eq!(config.whitelists(), None);
+        assert!(config.compression());
+
+        let config: Config = serde_yaml::from_str(
+            "
+            port: 5555
+            save_path: 'default'
+            debug: 0
+            seed: test
+            compression: false
+        ",
+        )?;
+        assert!(!config.compression());
         Ok(())
     }
 
     #[test]
-    fn seed() -> Result<()> {
-        let seed_key = "greyhounds";
+    fn accept_policy() -> Result<()> {
+        let config = make_config()?;
+        assert_eq!(config.accept(), AcceptPolicy::Prompt);
 
-        let yaml_config = format!(
+        let config: Config = serde_yaml::from_str(
             "
-            port: 5555 
+            port: 5555
             save_path: 'default'
-            secret:
-            - key: foo
-              value: bar
-            - key: baz
-              value: woo
-            message: 
-            - new message from me
-            - test message
-            debug: 1
-            seed: {seed_key}
+            debug: 0
+            seed: test
+            accept: saved
+        ",
+        )?;
+        assert_eq!(config.accept(), AcceptPolicy::Saved);
+        Ok(())
+    }
+
+    #[test]
+    fn replay_window() -> Result<()> {
+        let mut config = make_config()?;
+        assert_eq!(config.replay_window(), 600);
+        config.replay_window = Some(30);
+        assert_eq!(config.replay_window(), 30);
+        Ok(())
+    }
+
+    #[test]
+    fn mailbox() -> Result<()> {
+        let relay: Multiaddr = "/ip4/157.245.40.97/tcp/4001".parse()?;
+        let mut config = make_config()?;
+        assert_eq!(config.mailbox().peer(&relay)?, None);
+        assert_eq!(config.mailbox_window(), 7 * 24 * 60 * 60);
+
+        let peer_id = PeerId::random();
+        config.mailbox = Some(serde_yaml::from_str(&format!(
+            "
+            address: {peer_id}
+            max_size: 1 MB
         "
+        ))?);
+        let mailbox = config.mailbox();
+        let (mailbox_peer, address) = mailbox.peer(&relay)?.unwrap();
+        assert_eq!(mailbox_peer, peer_id);
+        assert_eq!(
+            address.to_string(),
+            format!("/ip4/157.245.40.97/tcp/4001/p2p-circuit/p2p/{peer_id}")
         );
-        let config: Config = serde_yaml::from_str(&yaml_config)?;
+        assert_eq!(mailbox.max_size(), 1_000_000);
+        assert_eq!(mailbox.retention(), 7 * 24 * 60 * 60);
+        config.replay_window = Some(mailbox.retention() + 1);
+        assert_eq!(config.mailbox_window(), mailbox.retention() + 1);
 
-        let padded_string = config.seed_key();
-        let padded_string = &padded_string[seed_key.len()..padded_string.len()];
-        assert_eq!(padded_string.len(), (32 - seed_key.len()));
+        let local = format!("/ip4/127.0.0.1/tcp/5555/p2p/{peer_id}");
+        let mailbox = Mailbox {
+            address: Some(local.clone()),
+            ..Default::default()
+        };
+        assert_eq!(mailbox.peer(&relay)?.unwrap().1.to_string(), local);
+
+        let mailbox = Mailbox {
+            address: Some("/ip4/127.0.0.1/tcp/5555".to_string()),
+            ..Default::default()
+        };
+        assert!(mailbox.peer(&relay).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn collision_policy() -> Result<()> {
+        let mut config = make_config()?;
+        assert_eq!(config.on_collision(), CollisionPolicy::Overwrite);
+        config.on_collision = Some(serde_yaml::from_str("version")?);
+        assert_eq!(config.on_collision(), CollisionPolicy::Version);
+        assert!(serde_yaml::from_str::<CollisionPolicy>("append").is_err());
         Ok(())
     }
 
     #[test]
-    fn pad_string() -> Result<()> {
-        let s = "hi".to_string();
+    fn directories() -> Result<()> {
+        let mut config = make_config()?;
+        assert_eq!(config.directories(), Directories::default());
+        assert!(config.directories().gitignore());
+
+        config.directories = Some(serde_yaml::from_str(
+            "
+            include: ['*.pem']
+            gitignore: false
+        ",
+        )?);
+        let directories = config.directories();
+        assert_eq!(directories.include(), vec!["*.pem".to_string()]);
+        assert!(directories.exclude().is_empty());
+        assert!(!directories.gitignore());
+        Ok(())
+    }
+
+    #[test]
+    fn imports() -> Result<()> {
+        let mut config = make_config()?;
+        assert!(config.imports().is_empty());
+
+        config.import = Some(serde_yaml::from_str(
+            "
+            - path: .env
+              except: [DEBUG]
+            - path: secret.yml
+              format: kubernetes
+              only: [USER]
+        ",
+        )?);
+        let imports = config.imports();
+        assert_eq!(imports[0].format, None);
+        assert!(imports[0].selects("API_KEY"));
+        assert!(!imports[0].selects("DEBUG"));
+        assert_eq!(imports[1].format, Some(ImportFormat::Kubernetes));
+        assert!(imports[1].selects("USER"));
+        assert!(!imports[1].selects("PASSWORD"));
+        Ok(())
+    }
+
+    #[test]
+    fn namespace() -> Result<()> {
+        let mut config = make_config()?;
+        assert!(config.namespace().is_empty());
+        config.project = Some("billing".to_string());
+        config.environment = Some("prod".to_string());
+        assert_eq!(config.namespace().to_string(), "billing@prod");
+        Ok(())
+    }
+
+    #[test]
+    fn expiry() -> Result<()> {
+        let config = make_config()?;
+        assert_eq!(config.expiry(), None);
+
+        let config: Config = serde_yaml::from_str(
+            "
+            port: 5555
+            save_path: default
+            debug: 0
+            seed: test
+            expires_in: 1h
+            burn_after_reading: true
+        ",
+        )?;
+        let expiry = config.expiry().unwrap();
+        assert_eq!(expiry.after, Some(3600));
+        assert!(expiry.burn);
+        let invalid = "
+            port: 5555
+            save_path: default
+            debug: 0
+            seed: test
+            expires_in: soon
+        ";
+        assert!(serde_yaml::from_str::<Config>(invalid).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn secret_history() -> Result<()> {
+        let mut config = make_config()?;
+        assert_eq!(config.secret_history(), 10);
+        config.secret_history = Some(0);
+        assert_eq!(config.secret_history(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn limits() -> Result<()> {
         let config = make_config()?;
-        let padded_str = config.pad_seed_key(s.clone());
+        assert_eq!(config.limits(), Limits::default());
 
-        assert_eq!(padded_str.len(), 32);
-        assert_ne!(s, padded_str);
-        assert!(padded_str.contains(&s));
+        let config: Config = serde_yaml::from_str(
+            "
+            port: 5555
+            save_path: 'default'
+            debug: 0
+            seed: test
+            limits:
+              max_file_size: 40 MB
+              max_request_size: 100000000
+              max_messages: 5
+        ",
+        )?;
+        let limits = config.limits();
+        assert_eq!(limits.max_file_size, Some(40_000_000));
+        assert_eq!(limits.max_request_size, Some(100_000_000));
+        assert_eq!(limits.max_items, None);
+        assert_eq!(limits.max_messages, Some(5));
+        Ok(())
+    }
 
+    #[test]
+    fn pull_rules() -> Result<()> {
+        let config = make_config()?;
+        assert!(config.pull_rules().is_empty());
+
+        let config: Config = serde_yaml::from_str(
+            "
+            port: 5555
+            save_path: 'default'
+            debug: 0
+            seed: test
+            pull:
+            - peer: dante
+              keys:
+              - STRIPE_KEY
+            - peer: 12D3KooWA768LzHMatxkjD1f9DrYW375GZJr6MHPCNEdDtHeTNRt
+              keys:
+              - '*'
+        ",
+        )?;
+        let rules = config.pull_rules();
+        assert!(rules[0].allows("STRIPE_KEY"));
+        assert!(!rules[0].allows("DATABASE_URL"));
+        assert!(rules[1].allows("DATABASE_URL"));
+        Ok(())
+    }
+
+    #[test]
+    fn lists() -> Result<()> {
+        let config = make_config()?;
+        assert_eq!(config.blacklists(), None);
+        assert_eq!(config.whitelists(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn seed() -> Result<()> {
+        let mut config = make_config()?;
+        let seed = "greyhounds".repeat(4);
+        config.seed = format!("{seed}a");
+        let keypair = config.keypair();
+        //the whole seed is used, not only its first 32 characters
+        config.seed = format!("{seed}b");
+        assert_ne!(config.keypair().public(), keypair.public());
+        Ok(())
+    }
+
+    #[test]
+    fn keypair_from_seed() -> Result<()> {
+        let config = make_config()?;
+        assert_eq!(config.keypair().public(), make_config()?.keypair().public());
+        Ok(())
+    }
+
+    #[test]
+    fn persisted_seed() -> Result<()> {
+        let dir = assert_fs::TempDir::new()?;
+        let seed = Config::load_or_create_seed(dir.path())?;
+        assert_eq!(seed.len(), 64);
+        assert_eq!(seed, Config::load_or_create_seed(dir.path())?);
+        dir.close()?;
         Ok(())
     }
 }
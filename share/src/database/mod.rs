@@ -14,7 +14,11 @@ use crate::network::Behaviour;
 
 use self::peer::ScsPeer;
 
+pub mod parcel;
 pub mod peer;
+pub mod provenance;
+pub mod receipt;
+pub mod seen_request;
 
 #[derive(Debug)]
 pub struct Store {
@@ -52,6 +56,84 @@ impl Store {
         )?;
         debug!("Executed schema creation for peer");
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS provenance (
+            id    INTEGER PRIMARY KEY,
+            item_type  TEXT NOT NULL,
+            name  TEXT NOT NULL,
+            digest  TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            signature TEXT NOT NULL,
+            signed_at INTEGER NOT NULL,
+            received_at TEXT NOT NULL,
+            session TEXT,
+            content_hash TEXT,
+            saved_as TEXT
+        )",
+            (),
+        )?;
+        //databases made before the delivery was recorded
+        if conn.prepare("SELECT session FROM provenance").is_err() {
+            conn.execute("ALTER TABLE provenance ADD COLUMN session TEXT", ())?;
+        }
+        //databases made before received files were recorded by their content
+        if conn.prepare("SELECT content_hash FROM provenance").is_err() {
+            conn.execute("ALTER TABLE provenance ADD COLUMN content_hash TEXT", ())?;
+            conn.execute("ALTER TABLE provenance ADD COLUMN saved_as TEXT", ())?;
+        }
+        debug!("Executed schema creation for provenance");
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS receipt (
+            id    INTEGER PRIMARY KEY,
+            item_type  TEXT NOT NULL,
+            name  TEXT NOT NULL,
+            digest  TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            receiver TEXT NOT NULL,
+            signature TEXT NOT NULL,
+            received_at INTEGER NOT NULL
+        )",
+            (),
+        )?;
+        debug!("Executed schema creation for receipt");
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS seen_request (
+            id    TEXT PRIMARY KEY,
+            sender TEXT NOT NULL,
+            sent_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL DEFAULT 0
+        )",
+            (),
+        )?;
+        //databases made before each request was kept for its own window keep the old ones for
+        //as long as a mailbox keeps deliveries by default
+        if conn.prepare("SELECT expires_at FROM seen_request").is_err() {
+            conn.execute(
+                "ALTER TABLE seen_request ADD COLUMN expires_at INTEGER NOT NULL DEFAULT 0",
+                (),
+            )?;
+            conn.execute(
+                "UPDATE seen_request SET expires_at = sent_at + 7 * 24 * 60 * 60",
+                (),
+            )?;
+        }
+        debug!("Executed schema creation for seen_request");
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS parcel (
+            id    INTEGER PRIMARY KEY,
+            sender TEXT NOT NULL,
+            recipient TEXT NOT NULL,
+            deposit BLOB NOT NULL,
+            size INTEGER NOT NULL,
+            deposited_at INTEGER NOT NULL
+        )",
+            (),
+        )?;
+        debug!("Executed schema creation for parcel");
+
         let settings = Store {
             conn: Arc::new(Mutex::new(conn)),
         };
@@ -129,4 +211,53 @@ mod tests {
         db_path.close()?;
         Ok(())
     }
+
+    #[test]
+    fn old_databases_migrated() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_old.db3")?;
+        let conn = rusqlite::Connection::open(db_path.path())?;
+        conn.execute(
+            "CREATE TABLE provenance (
+            id    INTEGER PRIMARY KEY,
+            item_type  TEXT NOT NULL,
+            name  TEXT NOT NULL,
+            digest  TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            signature TEXT NOT NULL,
+            signed_at INTEGER NOT NULL,
+            received_at TEXT NOT NULL
+        )",
+            (),
+        )?;
+        conn.execute(
+            "CREATE TABLE seen_request (
+            id    TEXT PRIMARY KEY,
+            sender TEXT NOT NULL,
+            sent_at INTEGER NOT NULL
+        )",
+            (),
+        )?;
+        conn.execute(
+            "INSERT INTO seen_request (id, sender, sent_at) VALUES ('01', 'peer', 1000)",
+            (),
+        )?;
+        drop(conn);
+
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        assert!(store
+            .get_conn_handle()
+            .prepare("SELECT session, content_hash, saved_as FROM provenance")
+            .is_ok());
+        let expires_at: i64 = store.get_conn_handle().query_row(
+            "SELECT expires_at FROM seen_request",
+            (),
+            |row| row.get(0),
+        )?;
+        assert_eq!(expires_at, 1000 + 7 * 24 * 60 * 60);
+        drop(store);
+        Store::initialize(Some(db_path.path().to_path_buf()))?;
+
+        db_path.close()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use rusqlite::{named_params, Row};
+use tracing::debug;
+
+use crate::item::Deposit;
+
+use super::Store;
+
+/// A deposit kept by a mailbox node until its recipient collects it or it expires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parcel {
+    id: Option<i64>,
+    sender: String,
+    recipient: String,
+    deposit: Vec<u8>,
+    size: u64,
+    deposited_at: i64,
+}
+
+impl TryFrom<&Row<'_>> for Parcel {
+    fn try_from(row: &Row<'_>) -> Result<Self> {
+        debug!("Creating Parcel from Row");
+
+        let parcel = Parcel {
+            id: row.get(0)?,
+            sender: row.get(1)?,
+            recipient: row.get(2)?,
+            deposit: row.get(3)?,
+            size: row.get(4)?,
+            deposited_at: row.get(5)?,
+        };
+        Ok(parcel)
+    }
+
+    type Error = anyhow::Error;
+}
+
+impl Parcel {
+    pub fn new(deposit: &Deposit, deposited_at: i64) -> Result<Parcel> {
+        Ok(Parcel {
+            id: None,
+            sender: deposit.sender.clone(),
+            recipient: deposit.recipient.clone(),
+            deposit: serde_cbor::to_vec(deposit)?,
+            size: deposit.manifest().size(),
+            deposited_at,
+        })
+    }
+
+    pub fn deposit(&self) -> Result<Deposit> {
+        Ok(serde_cbor::from_slice(&self.deposit)?)
+    }
+
+    /// Size of the deposit as it is kept, which is about what it takes in a CBOR response.
+    pub fn encoded_size(&self) -> u64 {
+        self.deposit.len() as u64
+    }
+
+    pub fn save(&self, store: &Store) -> Result<()> {
+        debug!("Saving Parcel");
+        let conn = store.get_conn_handle();
+        conn.execute(
+            "INSERT INTO parcel (sender, recipient, deposit, size, deposited_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                &self.sender,
+                &self.recipient,
+                &self.deposit,
+                &self.size,
+                &self.deposited_at,
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// The number and total size of the parcels kept for `recipient`.
+    pub fn usage_for(recipient: &str, store: &Store) -> Result<(usize, u64)> {
+        let conn = store.get_conn_handle();
+        let usage = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM parcel WHERE recipient = :recipient",
+            named_params! { ":recipient": recipient },
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(usage)
+    }
+
+    /// The number and total size of the parcels left by `sender`.
+    pub fn usage_from(sender: &str, store: &Store) -> Result<(usize, u64)> {
+        let conn = store.get_conn_handle();
+        let usage = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM parcel WHERE sender = :sender",
+            named_params! { ":sender": sender },
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(usage)
+    }
+
+    /// The number and total size of every parcel kept.
+    pub fn total_usage(store: &Store) -> Result<(usize, u64)> {
+        let conn = store.get_conn_handle();
+        let usage = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM parcel",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(usage)
+    }
+
+    /// The parcels kept for `recipient`, oldest first.
+    pub fn fetch_all(recipient: &str, store: &Store) -> Result<Vec<Parcel>> {
+        let conn = store.get_conn_handle();
+        let mut stmt = conn.prepare(
+            "SELECT id, sender, recipient, deposit, size, deposited_at FROM parcel WHERE recipient = :recipient ORDER BY deposited_at, id",
+        )?;
+        let iter = stmt.query_map(named_params! { ":recipient": recipient }, |row| {
+            Ok(Parcel::try_from(row).unwrap())
+        })?;
+        Ok(iter.filter_map(|parcel| parcel.ok()).collect())
+    }
+
+    pub fn delete(&self, store: &Store) -> Result<()> {
+        let conn = store.get_conn_handle();
+        conn.execute(
+            "DELETE FROM parcel WHERE id = :id",
+            named_params! { ":id": self.id },
+        )?;
+        Ok(())
+    }
+
+    /// Deletes parcels deposited before `deposited_at`.
+    pub fn prune(deposited_at: i64, store: &Store) -> Result<usize> {
+        let conn = store.get_conn_handle();
+        let pruned = conn.execute(
+            "DELETE FROM parcel WHERE deposited_at < :deposited_at",
+            named_params! { ":deposited_at": deposited_at },
+        )?;
+        Ok(pruned)
+    }
+}
@@ -0,0 +1,249 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use libp2p::PeerId;
+use rusqlite::{named_params, Row};
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::item::{Item, ItemSignature, SavedFile};
+
+use super::Store;
+
+/// Who sent a received item and their signature over it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    id: Option<i32>,
+    item_type: String,
+    name: String,
+    digest: String,
+    sender: String,
+    signature: String,
+    signed_at: i64,
+    received_at: String,
+    /// Id of the delivery the item came in, which pulled secrets do not have
+    session: Option<String>,
+    /// Hash of the content of a file, keyed to the recipient
+    content_hash: Option<String>,
+    /// Name a file was saved as under the save path, which differs from its own if it was renamed
+    saved_as: Option<String>,
+}
+
+impl TryFrom<&Row<'_>> for Provenance {
+    fn try_from(row: &Row<'_>) -> Result<Self> {
+        debug!("Creating Provenance from Row");
+
+        let provenance = Provenance {
+            id: row.get(0)?,
+            item_type: row.get(1)?,
+            name: row.get(2)?,
+            digest: row.get(3)?,
+            sender: row.get(4)?,
+            signature: row.get(5)?,
+            signed_at: row.get(6)?,
+            received_at: row.get(7)?,
+            session: row.get(8)?,
+            content_hash: row.get(9)?,
+            saved_as: row.get(10)?,
+        };
+        Ok(provenance)
+    }
+
+    type Error = anyhow::Error;
+}
+
+impl Provenance {
+    pub fn new(
+        item: &Item,
+        sender: PeerId,
+        signature: &ItemSignature,
+        session: Option<&[u8]>,
+        saved_as: Option<String>,
+    ) -> Provenance {
+        Provenance {
+            id: None,
+            item_type: format!("{:?}", item.item_type()),
+            name: item.name(),
+            digest: to_hex(&item.digest()),
+            sender: sender.to_string(),
+            signature: STANDARD.encode(&signature.signature),
+            signed_at: signature.signed_at,
+            received_at: OffsetDateTime::now_utc().to_string(),
+            session: session.map(to_hex),
+            content_hash: item.content_hash().as_deref().map(to_hex),
+            saved_as,
+        }
+    }
+
+    pub fn item_type(&self) -> &str {
+        &self.item_type
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn sender(&self) -> Result<PeerId> {
+        PeerId::from_str(&self.sender).map_err(|err| anyhow!("{}", err.to_string()))
+    }
+
+    pub fn session(&self) -> Option<&str> {
+        self.session.as_deref()
+    }
+
+    pub fn saved_as(&self) -> Option<&str> {
+        self.saved_as.as_deref()
+    }
+
+    pub fn signed_at(&self) -> Result<OffsetDateTime> {
+        Ok(OffsetDateTime::from_unix_timestamp(self.signed_at)?)
+    }
+
+    /// Checks the stored signature again, as the item was received by `recipient`.
+    pub fn verify(&self, recipient: &PeerId) -> Result<()> {
+        let signature = ItemSignature {
+            signed_at: self.signed_at,
+            signature: STANDARD.decode(&self.signature)?,
+        };
+        signature.verify(&self.sender()?, recipient, &from_hex(&self.digest)?)
+    }
+
+    pub fn save(&self, store: &Store) -> Result<()> {
+        debug!("Saving Provenance");
+        let conn = store.get_conn_handle();
+        conn.execute(
+            "INSERT INTO provenance (item_type, name, digest, sender, signature, signed_at, received_at, session, content_hash, saved_as) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            (
+                &self.item_type,
+                &self.name,
+                &self.digest,
+                &self.sender,
+                &self.signature,
+                &self.signed_at,
+                &self.received_at,
+                &self.session,
+                &self.content_hash,
+                &self.saved_as,
+            ),
+        )?;
+        Ok(())
+    }
+
+    pub fn fetch_all(store: &Store) -> Result<Vec<Provenance>> {
+        let conn = store.get_conn_handle();
+        let mut stmt = conn.prepare(
+            "SELECT id, item_type, name, digest, sender, signature, signed_at, received_at, session, content_hash, saved_as FROM provenance",
+        )?;
+        let iter = stmt.query_map([], |row| Ok(Provenance::try_from(row).unwrap()))?;
+        let records = iter.filter_map(|record| record.ok()).collect::<Vec<_>>();
+        Ok(records)
+    }
+
+    pub fn get_by_digest(digest: &[u8], store: &Store) -> Result<Option<Provenance>> {
+        let conn = store.get_conn_handle();
+        let mut statement = conn.prepare(
+            "SELECT id, item_type, name, digest, sender, signature, signed_at, received_at, session, content_hash, saved_as FROM provenance WHERE digest = :digest",
+        )?;
+        let iter = statement.query_map(named_params! { ":digest": to_hex(digest) }, |row| {
+            Ok(Provenance::try_from(row).unwrap())
+        })?;
+        let records = iter.filter_map(|record| record.ok()).collect::<Vec<_>>();
+        Ok(records.first().cloned())
+    }
+
+    /// Points the records of the file that was saved under the name `saved` was saved as to where
+    /// it was moved, or to nothing if it was overwritten, so they only name content still held.
+    pub fn displace(saved: &SavedFile, store: &Store) -> Result<usize> {
+        let conn = store.get_conn_handle();
+        let displaced = conn.execute(
+            "UPDATE provenance SET saved_as = :moved_to WHERE saved_as = :name",
+            named_params! { ":moved_to": saved.moved_to, ":name": saved.name },
+        )?;
+        Ok(displaced)
+    }
+
+    /// The files received with the content hashed to `content_hash`.
+    pub fn fetch_by_content_hash(content_hash: &[u8], store: &Store) -> Result<Vec<Provenance>> {
+        let conn = store.get_conn_handle();
+        let mut statement = conn.prepare(
+            "SELECT id, item_type, name, digest, sender, signature, signed_at, received_at, session, content_hash, saved_as FROM provenance WHERE content_hash = :content_hash",
+        )?;
+        let iter = statement.query_map(
+            named_params! { ":content_hash": to_hex(content_hash) },
+            |row| Ok(Provenance::try_from(row).unwrap()),
+        )?;
+        Ok(iter.filter_map(|record| record.ok()).collect())
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return Err(anyhow!("Invalid digest {hex}"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|err| anyhow!("{err}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::{from_hex, to_hex, Provenance};
+    use crate::{
+        database::Store,
+        item::{Item, ItemType},
+    };
+
+    #[test]
+    fn hex() -> Result<()> {
+        let bytes = vec![0, 1, 171, 255];
+        assert_eq!(to_hex(&bytes), "0001abff");
+        assert_eq!(from_hex("0001abff")?, bytes);
+        assert!(from_hex("abc").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn save_and_verify() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_provenance.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let mut item = Item::new("foo,bar".to_string(), ItemType::Secret)?;
+        item.seal(&recipient, false)?;
+        item.sign(&sender, &recipient)?;
+
+        let signature = item.verify(&sender.public().to_peer_id(), &recipient)?;
+        let session = [7u8; 16];
+        Provenance::new(
+            &item,
+            sender.public().to_peer_id(),
+            signature,
+            Some(&session),
+            None,
+        )
+        .save(&store)?;
+
+        let record = Provenance::get_by_digest(&item.digest(), &store)?.unwrap();
+        assert_eq!(record.name(), "foo");
+        assert_eq!(record.session(), Some(to_hex(&session).as_str()));
+        assert_eq!(record.sender()?, sender.public().to_peer_id());
+        record.verify(&recipient)?;
+
+        let other = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(record.verify(&other).is_err());
+        assert_eq!(Provenance::fetch_all(&store)?.len(), 1);
+
+        db_path.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use libp2p::PeerId;
+use rusqlite::Row;
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::item::{Acknowledgement, ManifestEntry};
+
+use super::{
+    provenance::{from_hex, to_hex},
+    Store,
+};
+
+/// Proof that an item was delivered, signed by the peer that received it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    id: Option<i32>,
+    item_type: String,
+    name: String,
+    digest: String,
+    sender: String,
+    receiver: String,
+    signature: String,
+    received_at: i64,
+}
+
+impl TryFrom<&Row<'_>> for Receipt {
+    fn try_from(row: &Row<'_>) -> Result<Self> {
+        debug!("Creating Receipt from Row");
+
+        let receipt = Receipt {
+            id: row.get(0)?,
+            item_type: row.get(1)?,
+            name: row.get(2)?,
+            digest: row.get(3)?,
+            sender: row.get(4)?,
+            receiver: row.get(5)?,
+            signature: row.get(6)?,
+            received_at: row.get(7)?,
+        };
+        Ok(receipt)
+    }
+
+    type Error = anyhow::Error;
+}
+
+impl Receipt {
+    /// Senders usually get a new `PeerId` for every run, so the one the item was sent with is kept.
+    pub fn new(
+        entry: &ManifestEntry,
+        sender: PeerId,
+        receiver: PeerId,
+        ack: &Acknowledgement,
+    ) -> Receipt {
+        Receipt {
+            id: None,
+            item_type: format!("{:?}", entry.item_type),
+            name: entry.name.clone(),
+            digest: to_hex(&ack.digest),
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            signature: STANDARD.encode(&ack.signature),
+            received_at: ack.received_at,
+        }
+    }
+
+    pub fn item_type(&self) -> &str {
+        &self.item_type
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn receiver(&self) -> Result<PeerId> {
+        PeerId::from_str(&self.receiver).map_err(|err| anyhow!("{}", err.to_string()))
+    }
+
+    pub fn received_at(&self) -> Result<OffsetDateTime> {
+        Ok(OffsetDateTime::from_unix_timestamp(self.received_at)?)
+    }
+
+    /// Checks the receiver's acknowledgement again.
+    pub fn verify(&self) -> Result<()> {
+        let sender =
+            PeerId::from_str(&self.sender).map_err(|err| anyhow!("{}", err.to_string()))?;
+        let ack = Acknowledgement {
+            digest: from_hex(&self.digest)?,
+            received_at: self.received_at,
+            signature: STANDARD.decode(&self.signature)?,
+        };
+        ack.verify(&self.receiver()?, &sender)
+    }
+
+    pub fn save(&self, store: &Store) -> Result<()> {
+        debug!("Saving Receipt");
+        let conn = store.get_conn_handle();
+        conn.execute(
+            "INSERT INTO receipt (item_type, name, digest, sender, receiver, signature, received_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (
+                &self.item_type,
+                &self.name,
+                &self.digest,
+                &self.sender,
+                &self.receiver,
+                &self.signature,
+                &self.received_at,
+            ),
+        )?;
+        Ok(())
+    }
+
+    pub fn fetch_all(store: &Store) -> Result<Vec<Receipt>> {
+        let conn = store.get_conn_handle();
+        let mut stmt = conn.prepare(
+            "SELECT id, item_type, name, digest, sender, receiver, signature, received_at FROM receipt ORDER BY received_at",
+        )?;
+        let iter = stmt.query_map([], |row| Ok(Receipt::try_from(row).unwrap()))?;
+        let receipts = iter.filter_map(|receipt| receipt.ok()).collect::<Vec<_>>();
+        Ok(receipts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::Receipt;
+    use crate::{
+        database::Store,
+        item::{Acknowledgement, Item, ItemType, Manifest},
+    };
+
+    #[test]
+    fn save_and_verify() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_receipt.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+
+        let receiver = Keypair::generate_ed25519();
+        let sender = Keypair::generate_ed25519().public().to_peer_id();
+        let item = Item::new("foo,bar".to_string(), ItemType::Secret)?;
+        let manifest = Manifest::new(std::slice::from_ref(&item));
+        let ack = Acknowledgement::sign(&receiver, &sender, &item.digest())?;
+
+        let receiver = receiver.public().to_peer_id();
+        Receipt::new(&manifest.entries[0], sender, receiver, &ack).save(&store)?;
+
+        let receipts = Receipt::fetch_all(&store)?;
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].name(), "foo");
+        assert_eq!(receipts[0].receiver()?, receiver);
+        receipts[0].verify()?;
+
+        let other = Keypair::generate_ed25519().public().to_peer_id();
+        let forged = Receipt::new(&manifest.entries[0], sender, other, &ack);
+        assert!(forged.verify().is_err());
+
+        db_path.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use libp2p::PeerId;
+use rusqlite::named_params;
+use tracing::debug;
+
+use super::{provenance::to_hex, Store};
+
+/// The id of a delivery that was already received, kept to refuse it if it is sent again until
+/// `expires_at`, when it is refused for its age anyway.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeenRequest {
+    id: String,
+    sender: String,
+    sent_at: i64,
+    expires_at: i64,
+}
+
+impl SeenRequest {
+    pub fn new(id: &[u8], sender: PeerId, sent_at: i64, expires_at: i64) -> SeenRequest {
+        SeenRequest {
+            id: to_hex(id),
+            sender: sender.to_string(),
+            sent_at,
+            expires_at,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn exists(&self, store: &Store) -> Result<bool> {
+        let conn = store.get_conn_handle();
+        let mut statement = conn.prepare("SELECT id FROM seen_request WHERE id = :id")?;
+        Ok(statement.exists(named_params! { ":id": &self.id })?)
+    }
+
+    pub fn save(&self, store: &Store) -> Result<()> {
+        debug!("Saving SeenRequest");
+        let conn = store.get_conn_handle();
+        conn.execute(
+            "INSERT INTO seen_request (id, sender, sent_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+            (&self.id, &self.sender, &self.sent_at, &self.expires_at),
+        )?;
+        Ok(())
+    }
+
+    /// Forgets requests that expired before `now`.
+    pub fn prune(now: i64, store: &Store) -> Result<usize> {
+        let conn = store.get_conn_handle();
+        let pruned = conn.execute(
+            "DELETE FROM seen_request WHERE expires_at < :now",
+            named_params! { ":now": now },
+        )?;
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::PeerId;
+
+    use super::SeenRequest;
+    use crate::database::Store;
+
+    #[test]
+    fn seen_and_pruned() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_seen_request.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+
+        let request = SeenRequest::new(&[1, 2, 3], PeerId::random(), 400, 1000);
+        assert!(!request.exists(&store)?);
+        request.save(&store)?;
+        assert!(request.exists(&store)?);
+        assert!(request.save(&store).is_err());
+
+        assert_eq!(SeenRequest::prune(1000, &store)?, 0);
+        assert_eq!(SeenRequest::prune(1001, &store)?, 1);
+        assert!(!request.exists(&store)?);
+
+        db_path.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use std::io;
+
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use libp2p::PeerId;
+use tracing::{error, info};
+
+use crate::{
+    config::{AcceptPolicy, Config},
+    database::{peer::ScsPeer, Store},
+    item::{format_size, Manifest, ManifestResponse},
+};
+
+/// Whether items are accepted, or the question to ask the owner about them.
+#[derive(Debug)]
+pub enum Approval {
+    Decided(ManifestResponse),
+    Ask(String),
+}
+
+/// Decides whether the items offered by `peer` in `manifest` should be sent and saved.
+pub fn approve(manifest: &Manifest, peer: PeerId, config: &Config, store: &Store) -> Approval {
+    let saved_peer = ScsPeer::get_by_peer_id(peer.to_string(), store)
+        .ok()
+        .flatten();
+    let sender = match &saved_peer {
+        Some(saved) => format!("{} ({peer})", saved.name()),
+        None => peer.to_string(),
+    };
+
+    info!("{sender} wants to send {manifest}");
+    for entry in &manifest.entries {
+        info!(
+            "  {:?} {} ({})",
+            entry.item_type,
+            entry.name,
+            format_size(entry.size)
+        );
+    }
+
+    match config.accept() {
+        AcceptPolicy::All => Approval::Decided(ManifestResponse::accepted()),
+        AcceptPolicy::Saved if saved_peer.is_some() => {
+            Approval::Decided(ManifestResponse::accepted())
+        }
+        _ => Approval::Ask(format!("Accept these items from {sender}?")),
+    }
+}
+
+/// Asks the owner `question` at the terminal, blocking until it is answered.
+pub fn confirm(question: &str) -> io::Result<bool> {
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(question)
+        .default(false)
+        .interact()
+}
+
+/// The response to items the owner was asked about.
+pub fn answered(answer: io::Result<bool>) -> ManifestResponse {
+    match answer {
+        Ok(true) => ManifestResponse::accepted(),
+        Ok(false) => ManifestResponse::declined("The receiver declined the items"),
+        Err(err) => {
+            error!("Failed to ask for approval: {err}. Pass `--accept-all` to accept items without being asked");
+            ManifestResponse::declined("The receiver could not approve the items")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+    use libp2p::PeerId;
+
+    use super::{answered, approve, Approval};
+    use crate::{
+        config::Config,
+        database::Store,
+        item::{Item, ItemType, Manifest},
+    };
+
+    #[test]
+    fn accept_all() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_approval.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let config: Config = serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: {:?}
+            debug: 0
+            seed: test
+            accept: all
+        ",
+            PathBuf::from(db_path.parent().unwrap())
+        ))?;
+
+        let items = vec![Item::new("foo,bar".to_string(), ItemType::Secret)?];
+        let approval = approve(&Manifest::new(&items), PeerId::random(), &config, &store);
+        assert!(matches!(approval, Approval::Decided(response) if response.accepted));
+
+        let config: Config = serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: {:?}
+            debug: 0
+            seed: test
+        ",
+            PathBuf::from(db_path.parent().unwrap())
+        ))?;
+        let approval = approve(&Manifest::new(&items), PeerId::random(), &config, &store);
+        assert!(matches!(approval, Approval::Ask(_)));
+        assert!(answered(Ok(true)).accepted);
+        assert!(!answered(Ok(false)).accepted);
+
+        db_path.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use libp2p::PeerId;
+
+use crate::{
+    config::Config,
+    database::{peer::ScsPeer, provenance::Provenance, Store},
+    item::{ItemType, Manifest},
+};
+
+/// The digests of the files offered in `manifest` whose content was received before and is still
+/// saved under `save_path`, whatever name it was saved as, so the sender can skip them. The
+/// content hashes recorded when the files were saved are trusted, so no file is read to answer.
+/// The answer tells whether we hold some content, so only saved peers get one.
+pub fn held_entries(
+    manifest: &Manifest,
+    peer: PeerId,
+    config: &Config,
+    store: &Store,
+) -> Vec<Vec<u8>> {
+    let saved_peer = ScsPeer::get_by_peer_id(peer.to_string(), store)
+        .ok()
+        .flatten();
+    if saved_peer.is_none() {
+        return vec![];
+    }
+
+    let save_path = config.save_path();
+    manifest
+        .entries
+        .iter()
+        .filter(|entry| entry.item_type == ItemType::File)
+        .filter(|entry| {
+            let hash = match &entry.content_hash {
+                Some(hash) => hash,
+                None => return false,
+            };
+            Provenance::fetch_by_content_hash(hash, store)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Provenance::saved_as)
+                .any(|saved_as| save_path.join(saved_as).is_file())
+        })
+        .map(|entry| entry.digest.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use assert_fs::prelude::FileWriteStr;
+    use libp2p::{identity::Keypair, Multiaddr, PeerId};
+
+    use super::held_entries;
+    use crate::{
+        config::Config,
+        database::{peer::ScsPeer, provenance::Provenance, Store},
+        item::{Item, ItemType, Manifest},
+    };
+
+    fn make_config(save_dir: &assert_fs::TempDir, on_collision: &str) -> Result<Config> {
+        Ok(serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: '{}'
+            debug: 0
+            seed: dedupe
+            on_collision: {on_collision}
+        ",
+            save_dir.path().to_str().unwrap()
+        ))?)
+    }
+
+    /// Saves `item` as if it was received from `sender`, recording where it went.
+    fn receive(item: &Item, sender: &Keypair, config: &Config, store: &Store) -> Result<()> {
+        let recipient = config.keypair().public().to_peer_id();
+        let mut item = item.clone();
+        item.sign(sender, &recipient)?;
+        let signature = item.verify(&sender.public().to_peer_id(), &recipient)?;
+        let saved = item.save(config)?;
+        if let Some(saved) = &saved {
+            Provenance::displace(saved, store)?;
+        }
+        let saved_as = saved.map(|saved| saved.name);
+        Provenance::new(
+            &item,
+            sender.public().to_peer_id(),
+            signature,
+            None,
+            saved_as,
+        )
+        .save(store)
+    }
+
+    fn sealed_file(path: &str, config: &Config) -> Result<Item> {
+        let mut item = Item::new(path.to_string(), ItemType::File)?;
+        item.seal(&config.keypair().public().to_peer_id(), true)?;
+        Ok(item)
+    }
+
+    #[test]
+    fn unchanged_files() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let db_path = assert_fs::NamedTempFile::new("scs_dedupe.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let config = make_config(&save_dir, "rename")?;
+        let sender = Keypair::generate_ed25519();
+        let peer = sender.public().to_peer_id();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/5555".parse()?;
+        ScsPeer::from((&address, "sender".to_string(), peer)).save(&store)?;
+
+        let fixture = assert_fs::NamedTempFile::new("fixture.json")?;
+        fixture.write_str("{\"users\": []}")?;
+        let path = fixture.path().to_str().unwrap().to_string();
+        let mut secret = Item::new("foo,bar".to_string(), ItemType::Secret)?;
+        secret.seal(&config.keypair().public().to_peer_id(), true)?;
+        let items = vec![sealed_file(&path, &config)?, secret];
+        assert!(held_entries(&Manifest::new(&items), peer, &config, &store).is_empty());
+
+        receive(&items[0], &sender, &config, &store)?;
+        let held = held_entries(&Manifest::new(&items), peer, &config, &store);
+        assert_eq!(held, vec![items[0].digest()]);
+
+        //peers that are not saved are not told what we hold
+        let stranger = PeerId::random();
+        assert!(held_entries(&Manifest::new(&items), stranger, &config, &store).is_empty());
+
+        //a changed file is sent again, and saved under another name
+        fixture.write_str("{\"users\": [\"dante\"]}")?;
+        let changed = sealed_file(&path, &config)?;
+        let manifest = Manifest::new(std::slice::from_ref(&changed));
+        assert!(held_entries(&manifest, peer, &config, &store).is_empty());
+        receive(&changed, &sender, &config, &store)?;
+        assert_eq!(
+            held_entries(&manifest, peer, &config, &store),
+            vec![changed.digest()]
+        );
+
+        fixture.close()?;
+        db_path.close()?;
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn earlier_versions() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let db_path = assert_fs::NamedTempFile::new("scs_dedupe_versions.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let config = make_config(&save_dir, "version")?;
+        let sender = Keypair::generate_ed25519();
+        let peer = sender.public().to_peer_id();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/5555".parse()?;
+        ScsPeer::from((&address, "sender".to_string(), peer)).save(&store)?;
+
+        let fixture = assert_fs::NamedTempFile::new("fixture.json")?;
+        let path = fixture.path().to_str().unwrap().to_string();
+        fixture.write_str("first")?;
+        let first = sealed_file(&path, &config)?;
+        receive(&first, &sender, &config, &store)?;
+        fixture.write_str("second")?;
+        receive(&sealed_file(&path, &config)?, &sender, &config, &store)?;
+
+        //the first content now lives in fixture.json.~1~
+        let manifest = Manifest::new(std::slice::from_ref(&first));
+        assert_eq!(
+            held_entries(&manifest, peer, &config, &store),
+            vec![first.digest()]
+        );
+
+        fixture.close()?;
+        db_path.close()?;
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn overwritten_files() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let db_path = assert_fs::NamedTempFile::new("scs_dedupe_overwritten.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let config = make_config(&save_dir, "overwrite")?;
+        let sender = Keypair::generate_ed25519();
+        let peer = sender.public().to_peer_id();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/5555".parse()?;
+        ScsPeer::from((&address, "sender".to_string(), peer)).save(&store)?;
+
+        let fixture = assert_fs::NamedTempFile::new("fixture.json")?;
+        let path = fixture.path().to_str().unwrap().to_string();
+        fixture.write_str("first")?;
+        let first = sealed_file(&path, &config)?;
+        receive(&first, &sender, &config, &store)?;
+        fixture.write_str("second")?;
+        let second = sealed_file(&path, &config)?;
+        receive(&second, &sender, &config, &store)?;
+
+        //the first content is gone, and the second too once the file is deleted
+        let manifest = Manifest::new(&[first, second.clone()]);
+        assert_eq!(
+            held_entries(&manifest, peer, &config, &store),
+            vec![second.digest()]
+        );
+        std::fs::remove_file(save_dir.path().join("fixture.json"))?;
+        assert!(held_entries(&manifest, peer, &config, &store).is_empty());
+
+        fixture.close()?;
+        db_path.close()?;
+        save_dir.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    config::Config,
+    database::{peer::ScsPeer, provenance::Provenance, Store},
+    item::{Namespace, Secret},
+    Cli,
+};
+
+/// Name of the Kubernetes `Secret` secrets are exported as.
+const KUBERNETES_NAME: &str = "scs-secrets";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ExportFormat {
+    /// `KEY=VALUE` lines
+    #[default]
+    Dotenv,
+    /// `export KEY='VALUE'` lines
+    Shell,
+    Json,
+    Yaml,
+    /// A Kubernetes `Secret` manifest
+    Kubernetes,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "dotenv" | "env" => Ok(ExportFormat::Dotenv),
+            "shell" | "sh" => Ok(ExportFormat::Shell),
+            "json" => Ok(ExportFormat::Json),
+            "yaml" | "yml" => Ok(ExportFormat::Yaml),
+            "kubernetes" | "k8s" => Ok(ExportFormat::Kubernetes),
+            _ => Err(
+                "Expected either 'dotenv' or 'shell' or 'json' or 'yaml' or 'kubernetes'"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+/// Decrypts received secrets and writes them out in `--format`, to `-o` or stdout.
+/// `-s` selects secrets by key, `-n` or `-r` by sender, `--session` by the delivery they came in
+/// and `--project` and `--env` by namespace. When a key was received more than once, the latest
+/// value is exported. Secrets that burn after reading are deleted once written.
+pub fn export(opts: &Cli, config: &Config, store: &Store) -> Result<()> {
+    let path = config.save_path();
+    let keypair = config.keypair();
+    let sender = match (&opts.remote_peer_id, &opts.name) {
+        (Some(peer), _) => Some(*peer),
+        (None, Some(name)) => Some(ScsPeer::get_by_name(name.to_string(), store)?.peer_id()?),
+        (None, None) => None,
+    };
+    let session = opts.session.as_ref().map(|session| session.to_lowercase());
+
+    let namespace = Namespace::new(opts.project.clone(), opts.env.clone())?;
+
+    let mut secrets: Vec<Secret> = vec![];
+    //the exported secrets as they are saved
+    let mut read = vec![];
+    for secret in Secret::fetch(&path, &namespace)? {
+        if let Some(keys) = &opts.secret {
+            if !keys.contains(&secret.key) && !keys.contains(&secret.name()) {
+                continue;
+            }
+        }
+        if sender.is_some() || session.is_some() {
+            let record = match Provenance::get_by_digest(&secret.digest(), store)? {
+                Some(record) => record,
+                None => continue,
+            };
+            if sender.is_some_and(|sender| record.sender().ok() != Some(sender)) {
+                continue;
+            }
+            let in_session = |session: &String| {
+                record
+                    .session()
+                    .is_some_and(|recorded| recorded.starts_with(session.as_str()))
+            };
+            if session.as_ref().is_some_and(|session| !in_session(session)) {
+                continue;
+            }
+        }
+
+        let opened = secret.open(&keypair, config.max_opened_size())?;
+        match secrets.iter().position(|saved| saved.key == opened.key) {
+            Some(at) if Namespace::of(&secrets[at]) != Namespace::of(&opened) => {
+                return Err(anyhow!(
+                    "{} and {} are both exported as {}. Pick one with --project and --env",
+                    secrets[at].name(),
+                    opened.name(),
+                    opened.key
+                ));
+            }
+            Some(at) => {
+                secrets[at] = opened;
+                read[at] = secret;
+            }
+            None => {
+                secrets.push(opened);
+                read.push(secret);
+            }
+        }
+    }
+
+    for key in opts.secret.iter().flatten() {
+        if !secrets
+            .iter()
+            .any(|secret| &secret.key == key || &secret.name() == key)
+        {
+            return Err(anyhow!("No secret named {key} has been received"));
+        }
+    }
+    if secrets.is_empty() {
+        return Err(anyhow!("No secrets to export"));
+    }
+
+    let contents = render(&secrets, opts.format.unwrap_or_default())?;
+    match &opts.output {
+        Some(output) => write_private(output, &contents)
+            .with_context(|| format!("Failed to write the secrets to {output}"))?,
+        None => io::stdout().lock().write_all(contents.as_bytes())?,
+    }
+    Secret::burn(&path, &read)?;
+    Ok(())
+}
+
+/// Writes `contents` to a file only its owner can read, even if it already existed.
+fn write_private(path: &str, contents: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+pub fn render(secrets: &[Secret], format: ExportFormat) -> Result<String> {
+    for secret in secrets {
+        check_key(&secret.key, format)?;
+    }
+
+    let map = |value: fn(&str) -> String| {
+        let mut map = Mapping::new();
+        for secret in secrets {
+            map.insert(secret.key.clone().into(), value(&secret.value).into());
+        }
+        map
+    };
+    let contents = match format {
+        ExportFormat::Dotenv => secrets
+            .iter()
+            .map(|secret| format!("{}={}\n", secret.key, dotenv_value(&secret.value)))
+            .collect(),
+        ExportFormat::Shell => secrets
+            .iter()
+            .map(|secret| format!("export {}={}\n", secret.key, shell_value(&secret.value)))
+            .collect(),
+        ExportFormat::Json => serde_json::to_string_pretty(&map(str::to_string))? + "\n",
+        ExportFormat::Yaml => serde_yaml::to_string(&map(str::to_string))?,
+        ExportFormat::Kubernetes => {
+            let mut metadata = Mapping::new();
+            metadata.insert("name".into(), KUBERNETES_NAME.into());
+            let mut manifest = Mapping::new();
+            manifest.insert("apiVersion".into(), "v1".into());
+            manifest.insert("kind".into(), "Secret".into());
+            manifest.insert("metadata".into(), Value::Mapping(metadata));
+            manifest.insert("type".into(), "Opaque".into());
+            let data = map(|value| STANDARD.encode(value));
+            manifest.insert("data".into(), Value::Mapping(data));
+            serde_yaml::to_string(&manifest)?
+        }
+    };
+    Ok(contents)
+}
+
+/// Shell variables are letters, digits and `_`, and dotenv files and Kubernetes also take `.` and
+/// `-`. JSON and YAML take any key.
+fn check_key(key: &str, format: ExportFormat) -> Result<()> {
+    let valid = match format {
+        ExportFormat::Json | ExportFormat::Yaml => true,
+        ExportFormat::Shell => {
+            !key.starts_with(|c: char| c.is_ascii_digit())
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        ExportFormat::Dotenv | ExportFormat::Kubernetes => {
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+        }
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(anyhow!("The key {key:?} can not be exported as {format:?}")),
+    }
+}
+
+/// Values are double quoted, with the escapes `scs` reads, unless they are plain words.
+fn dotenv_value(value: &str) -> String {
+    let plain = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-./:@,+%".contains(c));
+    if plain {
+        return value.to_string();
+    }
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Single quoted, so the shell expands nothing in it.
+fn shell_value(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use assert_fs::prelude::FileWriteStr;
+
+    use super::{render, ExportFormat};
+    use crate::{
+        config::Import,
+        item::{import_secrets, Secret},
+    };
+
+    fn secrets() -> Vec<Secret> {
+        [
+            ("API_KEY", "abc"),
+            ("GREETING", "it's \"quoted\"\n$HOME"),
+            ("EMPTY", ""),
+        ]
+        .iter()
+        .map(|(key, value)| Secret::new(key.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn formats() -> Result<()> {
+        assert_eq!(
+            render(&secrets(), ExportFormat::Dotenv)?,
+            "API_KEY=abc\nGREETING=\"it's \\\"quoted\\\"\\n$HOME\"\nEMPTY=\n"
+        );
+        assert_eq!(
+            render(&secrets(), ExportFormat::Shell)?,
+            "export API_KEY='abc'\nexport GREETING='it'\\''s \"quoted\"\n$HOME'\nexport EMPTY=''\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&secrets(), ExportFormat::Json)?)?;
+        assert_eq!(json["GREETING"], "it's \"quoted\"\n$HOME");
+
+        let spaced = vec![Secret::new("MY KEY".to_string(), "value".to_string())];
+        assert!(render(&spaced, ExportFormat::Shell).is_err());
+        assert!(render(&spaced, ExportFormat::Dotenv).is_err());
+        assert!(render(&spaced, ExportFormat::Yaml).is_ok());
+
+        assert_eq!("k8s".parse(), Ok(ExportFormat::Kubernetes));
+        assert!("toml".parse::<ExportFormat>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn imported_back() -> Result<()> {
+        for (name, format) in [
+            ("scs_export.env", ExportFormat::Dotenv),
+            ("scs_export.json", ExportFormat::Json),
+            ("scs_export.yml", ExportFormat::Yaml),
+            ("scs_export_k8s.yml", ExportFormat::Kubernetes),
+        ] {
+            let file = assert_fs::NamedTempFile::new(name)?;
+            file.write_str(&render(&secrets(), format)?)?;
+            let path = file.path().to_string_lossy().to_string();
+            let mut imported = import_secrets(&Import::new(&path))?;
+            imported.sort_by(|a, b| a.key.cmp(&b.key));
+            let mut expected = secrets();
+            expected.sort_by(|a, b| a.key.cmp(&b.key));
+            assert_eq!(imported, expected, "{format:?}");
+        }
+        Ok(())
+    }
+}
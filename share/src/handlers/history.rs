@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use time::OffsetDateTime;
+
+use crate::{
+    config::Config,
+    database::{peer::ScsPeer, Store},
+    handlers::read::latest,
+    item::{Namespace, Secret, Version},
+    Cli,
+};
+
+/// Lists the values each received secret had, oldest first, with who sent them and when.
+/// `-s` selects secrets by key, and `--project` and `--env` by namespace. Values are not printed;
+/// `scs rollback` makes an earlier one current again.
+pub fn history(opts: &Cli, config: &Config, store: &Store) -> Result<()> {
+    let namespace = Namespace::new(opts.project.clone(), opts.env.clone())?;
+    let secrets = Secret::fetch_versions(&config.save_path())?
+        .into_iter()
+        .filter(|secret| namespace.contains(secret))
+        .filter(|secret| match &opts.secret {
+            Some(keys) => keys.contains(&secret.key) || keys.contains(&secret.name()),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    if secrets.is_empty() {
+        return Err(anyhow!("No secrets have been received"));
+    }
+
+    for secret in secrets {
+        println!("{}", secret.name());
+        for version in secret.versions().iter().rev() {
+            let current = match version.number() == secret.number() {
+                true => " (current)",
+                false => "",
+            };
+            println!(
+                "  v{}{current}: {}",
+                version.number(),
+                describe(version.version.as_ref(), store)?
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Makes an earlier value of the secret `-s` names current again, as its next version. `--to`
+/// picks the version, as `scs history` shows it, otherwise the value before the current one is
+/// restored. `--project` and `--env` tell apart a key received in several namespaces.
+pub fn rollback(opts: &Cli, config: &Config) -> Result<()> {
+    let key = match opts.secret.as_deref() {
+        Some([key]) => key,
+        _ => return Err(anyhow!("Name the one secret to roll back with -s")),
+    };
+    let path = config.save_path();
+    let namespace = Namespace::new(opts.project.clone(), opts.env.clone())?;
+    let secrets = Secret::fetch_versions(&path)?
+        .into_iter()
+        .filter(|secret| namespace.contains(secret))
+        .collect::<Vec<_>>();
+    let secret =
+        latest(&secrets, key)?.ok_or_else(|| anyhow!("No secret named {key} has been received"))?;
+
+    let restored = secret.rollback(&path, opts.to, config.secret_history())?;
+    let from = restored
+        .version
+        .as_ref()
+        .and_then(|version| version.restored_from)
+        .unwrap_or_default();
+    println!(
+        "{}: v{from} restored as v{}",
+        restored.name(),
+        restored.number()
+    );
+    Ok(())
+}
+
+fn describe(version: Option<&Version>, store: &Store) -> Result<String> {
+    let version = version.cloned().unwrap_or_default();
+    let mut description = match &version.sender {
+        Some(sender) => match ScsPeer::get_by_peer_id(sender.clone(), store)? {
+            Some(peer) => format!("sent by {} ({sender})", peer.name()),
+            None => format!("sent by {sender}"),
+        },
+        None => "sender unknown".to_string(),
+    };
+    if let Some(received_at) = version.received_at {
+        let received_at = OffsetDateTime::from_unix_timestamp(received_at)?;
+        description.push_str(&format!(", received at {received_at}"));
+    }
+    if let Some(restored_from) = version.restored_from {
+        description.push_str(&format!(", restored from v{restored_from}"));
+    }
+    Ok(description)
+}
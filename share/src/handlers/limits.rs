@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    config::Config,
+    item::{format_size, ItemType, Manifest},
+};
+
+/// Checks the items offered in `manifest` against the configured limits and the space left
+/// in `save_path`. `messages_received` is the number of messages already accepted from the peer.
+pub fn check_limits(manifest: &Manifest, messages_received: usize, config: &Config) -> Result<()> {
+    let limits = config.limits();
+
+    if let Some(max) = limits.max_items {
+        if manifest.entries.len() > max {
+            return Err(anyhow!(
+                "{} items were offered, but at most {max} are accepted at once",
+                manifest.entries.len()
+            ));
+        }
+    }
+
+    if let Some(max) = limits.max_request_size {
+        if manifest.size() > max {
+            return Err(anyhow!(
+                "The items are {} in total, but at most {} are accepted at once",
+                format_size(manifest.size()),
+                format_size(max)
+            ));
+        }
+    }
+
+    if let Some(max) = limits.max_file_size {
+        let files = manifest
+            .entries
+            .iter()
+            .filter(|entry| entry.item_type == ItemType::File);
+        for entry in files {
+            if entry.size > max {
+                return Err(anyhow!(
+                    "{} is {}, but files of at most {} are accepted",
+                    entry.name,
+                    format_size(entry.size),
+                    format_size(max)
+                ));
+            }
+        }
+    }
+
+    if let Some(max) = limits.max_messages {
+        let messages = manifest.count(ItemType::Message);
+        if messages_received + messages > max {
+            return Err(anyhow!(
+                "{messages} messages were offered, but only {} more are accepted",
+                max.saturating_sub(messages_received)
+            ));
+        }
+    }
+
+    let save_path = config.save_path();
+    let available = fs2::available_space(&save_path)
+        .with_context(|| format!("Failed to check the space left in {}", save_path.display()))?;
+    if manifest.size() > available {
+        return Err(anyhow!(
+            "The items are {} in total, but only {} of space is left",
+            format_size(manifest.size()),
+            format_size(available)
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use assert_fs::prelude::FileWriteBin;
+
+    use super::check_limits;
+    use crate::{
+        config::Config,
+        item::{Item, ItemType, Manifest},
+    };
+
+    fn make_config(limits: &str) -> Result<Config> {
+        let save_path = std::env::temp_dir();
+        let config = serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: {save_path:?}
+            debug: 0
+            seed: test
+            limits:
+            {limits}
+        "
+        ))?;
+        Ok(config)
+    }
+
+    #[test]
+    fn within_limits() -> Result<()> {
+        let config = make_config("  max_items: 2")?;
+        let items = vec![Item::new("foo,bar".to_string(), ItemType::Secret)?];
+        check_limits(&Manifest::new(&items), 0, &config)?;
+        Ok(())
+    }
+
+    #[test]
+    fn too_many_items() -> Result<()> {
+        let config = make_config("  max_items: 1")?;
+        let items = vec![
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+            Item::new("baz,woo".to_string(), ItemType::Secret)?,
+        ];
+        let err = check_limits(&Manifest::new(&items), 0, &config).unwrap_err();
+        assert!(err.to_string().contains("at most 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn large_file() -> Result<()> {
+        let file = assert_fs::NamedTempFile::new("large.bin")?;
+        file.write_binary(&[0u8; 2000])?;
+        let config = make_config("  max_file_size: 1 KB")?;
+        let items = vec![Item::new(
+            file.path().to_str().unwrap().to_string(),
+            ItemType::File,
+        )?];
+
+        let err = check_limits(&Manifest::new(&items), 0, &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "large.bin is 2.0 KB, but files of at most 1.0 KB are accepted"
+        );
+        file.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn message_quota() -> Result<()> {
+        let config = make_config("  max_messages: 2")?;
+        let items = vec![Item::new("hi".to_string(), ItemType::Message)?];
+        let manifest = Manifest::new(&items);
+        check_limits(&manifest, 1, &config)?;
+
+        let err = check_limits(&manifest, 2, &config).unwrap_err();
+        assert!(err.to_string().contains("only 0 more"));
+        Ok(())
+    }
+}
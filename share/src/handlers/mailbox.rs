@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Result};
+use libp2p::PeerId;
+use time::OffsetDateTime;
+
+use crate::{
+    config::Config,
+    database::{parcel::Parcel, Store},
+    item::{format_size, Deposit},
+};
+
+/// Keeps a deposit from `sender` until its recipient collects it, within the limits of the mailbox:
+/// for the recipient, for the sender, and for everyone. The sender is the connected peer, whatever
+/// the deposit claims.
+pub fn keep_deposit(
+    mut deposit: Deposit,
+    sender: PeerId,
+    config: &Config,
+    store: &Store,
+) -> Result<()> {
+    deposit.sender = sender.to_string();
+    let recipient = deposit.recipient()?;
+
+    let mailbox = config.mailbox();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    Parcel::prune(now - mailbox.retention() as i64, store)?;
+
+    let deposit_size = deposit.manifest().size();
+    let quotas = [
+        (
+            format!("for {recipient}"),
+            Parcel::usage_for(&deposit.recipient, store)?,
+            mailbox.max_deposits(),
+            mailbox.max_size(),
+        ),
+        (
+            format!("from {sender}"),
+            Parcel::usage_from(&deposit.sender, store)?,
+            mailbox.max_deposits(),
+            mailbox.max_size(),
+        ),
+        (
+            "in all".to_string(),
+            Parcel::total_usage(store)?,
+            usize::MAX,
+            mailbox.max_total_size(),
+        ),
+    ];
+    for (whose, (count, size), max_deposits, max_size) in quotas {
+        if count >= max_deposits {
+            return Err(anyhow!(
+                "The mailbox already keeps {count} deliveries {whose}"
+            ));
+        }
+        if size + deposit_size > max_size {
+            return Err(anyhow!(
+                "The mailbox keeps {} {whose} and cannot take {} more, as it keeps at most {}",
+                format_size(size),
+                format_size(deposit_size),
+                format_size(max_size)
+            ));
+        }
+    }
+
+    Parcel::new(&deposit, now)?.save(store)
+}
+
+/// Deletes the deposits kept for `recipient` that it acknowledged, by the ids of their deliveries,
+/// and hands over the oldest of the rest up to `limit` bytes. One is handed over whatever its size,
+/// and the recipient collects again until none are left.
+pub fn collect_deposits(
+    recipient: PeerId,
+    acknowledged: &[Vec<u8>],
+    limit: u64,
+    config: &Config,
+    store: &Store,
+) -> Result<Vec<Deposit>> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    Parcel::prune(now - config.mailbox().retention() as i64, store)?;
+
+    let mut deposits = vec![];
+    let mut size = 0;
+    let mut full = false;
+    for parcel in Parcel::fetch_all(&recipient.to_string(), store)? {
+        let deposit = parcel.deposit()?;
+        if acknowledged.contains(&deposit.delivery.id) {
+            parcel.delete(store)?;
+            continue;
+        }
+        size += parcel.encoded_size();
+        full |= !deposits.is_empty() && size > limit;
+        if !full {
+            deposits.push(deposit);
+        }
+    }
+    Ok(deposits)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::{identity::Keypair, PeerId};
+
+    use super::{collect_deposits, keep_deposit};
+    use crate::{
+        config::Config,
+        database::{parcel::Parcel, Store},
+        item::{Delivery, Deposit, Item, ItemType},
+    };
+
+    fn make_deposit(recipient: PeerId, message: &str) -> Result<Deposit> {
+        let sender = Keypair::generate_ed25519();
+        let mut item = Item::new(message.to_string(), ItemType::Message)?;
+        item.seal(&recipient, true)?;
+        item.sign(&sender, &recipient)?;
+        let delivery = Delivery::new(vec![item], &sender, &recipient)?;
+        Ok(Deposit::new(
+            sender.public().to_peer_id(),
+            recipient,
+            delivery,
+        ))
+    }
+
+    fn make_config(mailbox: &str) -> Result<Config> {
+        Ok(serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: 'default'
+            debug: 0
+            seed: test
+            mailbox:
+              {mailbox}
+        "
+        ))?)
+    }
+
+    #[test]
+    fn deposits_collected_once() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_mailbox.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let config = make_config("max_deposits: 2")?;
+
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let sender = PeerId::random();
+        keep_deposit(make_deposit(recipient, "hi")?, sender, &config, &store)?;
+        keep_deposit(make_deposit(recipient, "there")?, sender, &config, &store)?;
+        let err =
+            keep_deposit(make_deposit(recipient, "again")?, sender, &config, &store).unwrap_err();
+        assert!(err.to_string().contains("already keeps 2 deliveries"));
+
+        let other = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(collect_deposits(other, &[], u64::MAX, &config, &store)?.is_empty());
+
+        //deposits are only deleted once the recipient acknowledges them
+        let deposits = collect_deposits(recipient, &[], u64::MAX, &config, &store)?;
+        assert_eq!(deposits.len(), 2);
+        assert_eq!(deposits[0].sender()?, sender);
+        assert_eq!(
+            collect_deposits(recipient, &[], u64::MAX, &config, &store)?,
+            deposits
+        );
+        let acknowledged = vec![deposits[0].delivery.id.clone()];
+        let rest = collect_deposits(recipient, &acknowledged, u64::MAX, &config, &store)?;
+        assert_eq!(rest, deposits[1..]);
+
+        //a collection holds one deposit at least, and no more than the limit
+        keep_deposit(make_deposit(recipient, "again")?, sender, &config, &store)?;
+        assert_eq!(collect_deposits(recipient, &[], 1, &config, &store)?, rest);
+        assert_eq!(
+            collect_deposits(recipient, &[], u64::MAX, &config, &store)?.len(),
+            2
+        );
+
+        db_path.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn retention_limits() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_mailbox_retention.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let sender = PeerId::random();
+        let deposit = make_deposit(recipient, "hi there")?;
+        let config = make_config(&format!("max_size: {}", deposit.manifest().size() + 1))?;
+
+        keep_deposit(deposit, sender, &config, &store)?;
+        let err =
+            keep_deposit(make_deposit(recipient, "foo")?, sender, &config, &store).unwrap_err();
+        assert!(err.to_string().contains("cannot take"));
+
+        //deposits older than the retention are dropped
+        let old = Parcel::new(&make_deposit(recipient, "old")?, 0)?;
+        old.save(&store)?;
+        assert_eq!(
+            collect_deposits(recipient, &[], u64::MAX, &config, &store)?.len(),
+            1
+        );
+
+        db_path.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn sender_and_total_limits() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_mailbox_senders.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let sender = PeerId::random();
+        let recipient = || Keypair::generate_ed25519().public().to_peer_id();
+
+        let config = make_config("max_deposits: 1")?;
+        keep_deposit(make_deposit(recipient(), "hi")?, sender, &config, &store)?;
+        let err =
+            keep_deposit(make_deposit(recipient(), "there")?, sender, &config, &store).unwrap_err();
+        assert!(err.to_string().contains(&format!("from {sender}")));
+
+        let deposit = make_deposit(recipient(), "hi there")?;
+        let kept = Parcel::total_usage(&store)?.1;
+        let config = make_config(&format!(
+            "max_total_size: {}",
+            kept + deposit.manifest().size()
+        ))?;
+        keep_deposit(deposit, PeerId::random(), &config, &store)?;
+        let err = keep_deposit(
+            make_deposit(recipient(), "foo")?,
+            PeerId::random(),
+            &config,
+            &store,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("in all"));
+
+        db_path.close()?;
+        Ok(())
+    }
+}
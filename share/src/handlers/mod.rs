@@ -1 +1,12 @@
+pub mod approval;
+pub mod dedupe;
+pub mod export;
+pub mod history;
+pub mod limits;
+pub mod mailbox;
+pub mod pull;
+pub mod read;
+pub mod receipts;
+pub mod replay;
 pub mod security;
+pub mod verify;
@@ -0,0 +1,207 @@
+use std::io;
+
+use anyhow::Result;
+use libp2p::PeerId;
+use tracing::{error, info};
+
+use crate::{
+    config::Config,
+    database::{peer::ScsPeer, Store},
+    item::Secret,
+};
+
+/// The keys a peer may pull as allowed in the config, and the ones the owner has to be asked about.
+#[derive(Debug)]
+pub struct PullAuthorization {
+    requester: String,
+    pub allowed: Vec<String>,
+    pub asked: Vec<String>,
+}
+
+impl PullAuthorization {
+    pub fn question(&self) -> String {
+        format!("Let {} pull {}?", self.requester, self.asked.join(", "))
+    }
+
+    /// The keys that may be pulled once the owner answered.
+    pub fn answered(mut self, answer: io::Result<bool>) -> Vec<String> {
+        match answer {
+            Ok(true) => self.allowed.extend(self.asked),
+            Ok(false) => {}
+            Err(err) => error!(
+                "Failed to ask whether {} may pull {}: {err}",
+                self.requester,
+                self.asked.join(", ")
+            ),
+        }
+        self.allowed
+    }
+}
+
+/// Sorts the keys `peer` requested into the ones allowed by the `pull` rules in the config, which
+/// are given without asking, and the ones the owner is asked about.
+pub fn authorize_pull(
+    keys: &[String],
+    peer: PeerId,
+    config: &Config,
+    store: &Store,
+) -> PullAuthorization {
+    let saved_peer = ScsPeer::get_by_peer_id(peer.to_string(), store)
+        .ok()
+        .flatten();
+    let requester = match &saved_peer {
+        Some(saved) => format!("{} ({peer})", saved.name()),
+        None => peer.to_string(),
+    };
+
+    let rules = config
+        .pull_rules()
+        .into_iter()
+        .filter(|rule| {
+            rule.peer == peer.to_string()
+                || saved_peer
+                    .as_ref()
+                    .map(|saved| saved.name() == rule.peer)
+                    .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    let (allowed, asked): (Vec<String>, Vec<String>) = keys
+        .iter()
+        .cloned()
+        .partition(|key| rules.iter().any(|rule| rule.allows(key)));
+
+    if !allowed.is_empty() {
+        info!(
+            "{requester} pulled {} as allowed in the config",
+            allowed.join(", ")
+        );
+    }
+    PullAuthorization {
+        requester,
+        allowed,
+        asked,
+    }
+}
+
+/// Looks up `keys` in the secrets of the config, then in the secrets received so far. Secrets in
+/// a namespace are named with it, e.g. `billing@prod/DATABASE_URL`. Received secrets that burn
+/// after reading are deleted once found, so they can only be pulled once.
+/// Returns the secrets found and the keys that were not.
+pub fn find_secrets(keys: &[String], config: &Config) -> Result<(Vec<Secret>, Vec<String>)> {
+    let configured = config.secret().unwrap_or_default();
+    let received = Secret::fetch_all(&config.save_path())?;
+    let keypair = config.keypair();
+
+    let mut found = vec![];
+    let mut missing = vec![];
+    let mut read = vec![];
+    for key in keys {
+        let secret = match configured.iter().find(|secret| &secret.name() == key) {
+            Some(secret) => Some(secret.clone()),
+            None => match received.iter().rev().find(|secret| &secret.name() == key) {
+                Some(secret) => {
+                    read.push(secret.clone());
+                    Some(secret.open(&keypair, config.max_opened_size())?)
+                }
+                None => None,
+            },
+        };
+        match secret {
+            Some(secret) => found.push(secret),
+            None => missing.push(key.clone()),
+        }
+    }
+    Secret::burn(&config.save_path(), &read)?;
+    Ok((found, missing))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::PeerId;
+
+    use super::{authorize_pull, find_secrets};
+    use crate::{
+        config::Config,
+        database::Store,
+        item::{Expiry, Item, ItemType, Secret},
+    };
+
+    fn make_config(save_path: &std::path::Path, peer: PeerId) -> Result<Config> {
+        let config = serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: {save_path:?}
+            debug: 0
+            seed: test
+            secret:
+            - key: STRIPE_KEY
+              value: sk_test
+            pull:
+            - peer: {peer}
+              keys:
+              - STRIPE_KEY
+              - DATABASE_URL
+        "
+        ))?;
+        Ok(config)
+    }
+
+    #[test]
+    fn allowed_keys() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_pull.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let peer = PeerId::random();
+        let config = make_config(db_path.parent().unwrap(), peer)?;
+
+        let keys = vec!["STRIPE_KEY".to_string(), "GITHUB_TOKEN".to_string()];
+        let authorization = authorize_pull(&keys, peer, &config, &store);
+        assert_eq!(authorization.allowed, keys[..1]);
+        assert_eq!(authorization.asked, keys[1..]);
+        assert_eq!(authorization.answered(Ok(false)), keys[..1]);
+
+        db_path.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn configured_and_received_secrets() -> Result<()> {
+        let dir = assert_fs::TempDir::new()?;
+        let config = make_config(dir.path(), PeerId::random())?;
+        Item::new("DATABASE_URL,postgres://db".to_string(), ItemType::Secret)?.save(&config)?;
+
+        let keys = vec![
+            "STRIPE_KEY".to_string(),
+            "DATABASE_URL".to_string(),
+            "MISSING".to_string(),
+        ];
+        let (secrets, missing) = find_secrets(&keys, &config)?;
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(secrets[0].value, "sk_test");
+        assert_eq!(secrets[1].value, "postgres://db");
+        assert_eq!(missing, vec!["MISSING".to_string()]);
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn burned_secrets() -> Result<()> {
+        let dir = assert_fs::TempDir::new()?;
+        let config = make_config(dir.path(), PeerId::random())?;
+        let mut secret = Secret::new("ONE_TIME".to_string(), "b".to_string());
+        secret.expiry = Expiry::new(None, true);
+        secret.save_secret(&config.save_path(), 10)?;
+
+        let keys = vec!["ONE_TIME".to_string()];
+        let (secrets, _) = find_secrets(&keys, &config)?;
+        assert_eq!(secrets[0].value, "b");
+        assert!(secrets[0].burns());
+        let (secrets, missing) = find_secrets(&keys, &config)?;
+        assert!(secrets.is_empty());
+        assert_eq!(missing, keys);
+
+        dir.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    config::Config,
+    database::{peer::ScsPeer, Store},
+    item::{ItemFile, ItemMessage, Namespace, Secret, Transcript},
+    Cli,
+};
+
+/// Decrypts received items and prints them.
+/// `-s` selects secrets by key, `-f` files by name and `-n` or `-r` the transcript of a chat with
+/// a peer, otherwise all secrets and messages are printed. `--project` and `--env` select the
+/// namespace secrets are read from. Secrets that burn after reading are deleted once printed.
+/// A file selected with `-f` is written to `-o` instead, with the mode and modification time it
+/// was sent with.
+pub fn read(opts: &Cli, config: &Config, store: &Store) -> Result<()> {
+    let path = config.save_path();
+    let keypair = config.keypair();
+    let limit = config.max_opened_size();
+    let mut stdout = io::stdout().lock();
+
+    let peer = match (&opts.remote_peer_id, &opts.name) {
+        (Some(peer), _) => Some(*peer),
+        (None, Some(name)) => Some(ScsPeer::get_by_name(name.to_string(), store)?.peer_id()?),
+        (None, None) => None,
+    };
+    if let Some(peer) = peer {
+        let lines = Transcript::new(&path, &peer).read(&keypair, limit)?;
+        if lines.is_empty() {
+            return Err(anyhow!("No chat with {peer} has been saved"));
+        }
+        for line in lines {
+            writeln!(stdout, "{line}")?;
+        }
+        return Ok(());
+    }
+
+    if let Some(names) = &opts.file {
+        if let Some(output) = &opts.output {
+            let [name] = names.as_slice() else {
+                return Err(anyhow!("Only one file can be written to {output}"));
+            };
+            return ItemFile::extract_saved(&path, name, Path::new(output), &keypair, limit)
+                .with_context(|| format!("Failed to write {name} to {output}"));
+        }
+        for name in names {
+            let data = ItemFile::read_saved(&path, name, &keypair, limit)?;
+            stdout.write_all(&data)?;
+        }
+        return Ok(());
+    }
+
+    let namespace = Namespace::new(opts.project.clone(), opts.env.clone())?;
+    let secrets = Secret::fetch(&path, &namespace)?;
+    match &opts.secret {
+        Some(keys) => {
+            let mut read = vec![];
+            for key in keys {
+                let secret = latest(&secrets, key)?
+                    .ok_or_else(|| anyhow!("No secret named {key} has been received"))?;
+                writeln!(stdout, "{}", secret.open(&keypair, limit)?.value)?;
+                read.push(secret.clone());
+            }
+            Secret::burn(&path, &read)?;
+        }
+        None => {
+            for secret in &secrets {
+                let secret = secret.open(&keypair, limit)?;
+                writeln!(stdout, "{}={}", secret.name(), secret.value)?;
+            }
+            for message in ItemMessage::fetch_all(&path)? {
+                writeln!(stdout, "{}", message.open(&keypair, limit)?.msg())?;
+            }
+            Secret::burn(&path, &secrets)?;
+        }
+    }
+    Ok(())
+}
+
+/// The secret last received as `key`, given either as it is or with its namespace. Keys
+/// received in several namespaces have to be told apart.
+pub fn latest<'a>(secrets: &'a [Secret], key: &str) -> Result<Option<&'a Secret>> {
+    let named = secrets
+        .iter()
+        .filter(|secret| secret.key == key || secret.name() == key)
+        .collect::<Vec<_>>();
+    let mut namespaces: Vec<Namespace> = vec![];
+    for namespace in named.iter().map(|secret| Namespace::of(secret)) {
+        if !namespaces.contains(&namespace) {
+            namespaces.push(namespace);
+        }
+    }
+    if namespaces.len() > 1 {
+        let namespaces = namespaces
+            .iter()
+            .map(|namespace| match namespace.is_empty() {
+                true => "no namespace".to_string(),
+                false => namespace.to_string(),
+            })
+            .collect::<Vec<_>>();
+        return Err(anyhow!(
+            "{key} was received in {}. Pick one with --project and --env",
+            namespaces.join(", ")
+        ));
+    }
+    Ok(named.last().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::latest;
+    use crate::item::{Namespace, Secret};
+
+    #[test]
+    fn namespaced_keys() -> Result<()> {
+        let mut secrets = vec![];
+        for (environment, value) in [("staging", "a"), ("prod", "b"), ("prod", "c")] {
+            let mut secret = Secret::new("DATABASE_URL".into(), value.into());
+            Namespace::new(None, Some(environment.into()))?.apply(&mut secret);
+            secrets.push(secret);
+        }
+
+        assert!(latest(&secrets, "DATABASE_URL").is_err());
+        assert_eq!(latest(&secrets, "@prod/DATABASE_URL")?.unwrap().value, "c");
+        assert_eq!(latest(&secrets[1..], "DATABASE_URL")?.unwrap().value, "c");
+        assert!(latest(&secrets, "API_KEY")?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Result};
+
+use crate::{
+    database::{peer::ScsPeer, receipt::Receipt, Store},
+    Cli,
+};
+
+/// Lists what was delivered to whom, checking each receiver's acknowledgement again.
+/// `-s` selects secrets by key and `-f` files by name, otherwise every receipt is listed.
+pub fn receipts(opts: &Cli, store: &Store) -> Result<()> {
+    let select_all = opts.secret.is_none() && opts.file.is_none();
+    let selected = |receipt: &Receipt| {
+        let names = match receipt.item_type() {
+            "Secret" => &opts.secret,
+            "File" => &opts.file,
+            _ => &None,
+        };
+        select_all
+            || names
+                .as_ref()
+                .map(|names| names.iter().any(|name| name == receipt.name()))
+                .unwrap_or(false)
+    };
+
+    let receipts = Receipt::fetch_all(store)?
+        .into_iter()
+        .filter(selected)
+        .collect::<Vec<_>>();
+    if receipts.is_empty() {
+        println!("No receipts");
+        return Ok(());
+    }
+
+    let mut failures = 0;
+    for receipt in receipts {
+        let label = match receipt.item_type() {
+            "Message" => "message".to_string(),
+            item_type => format!("{} {}", item_type.to_lowercase(), receipt.name()),
+        };
+        let receiver = receipt.receiver()?;
+        let receiver = match ScsPeer::get_by_peer_id(receiver.to_string(), store)? {
+            Some(peer) => format!("{} ({receiver})", peer.name()),
+            None => receiver.to_string(),
+        };
+
+        match receipt.verify() {
+            Ok(_) => println!(
+                "{label}: delivered to {receiver}, received at {}",
+                receipt.received_at()?
+            ),
+            Err(err) => {
+                println!("{label}: receipt from {receiver} does not verify: {err}");
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow!("{failures} receipts could not be verified"));
+    }
+    Ok(())
+}
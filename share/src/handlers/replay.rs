@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use libp2p::PeerId;
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{
+    config::Config,
+    database::{seen_request::SeenRequest, Store},
+    item::{Delivery, Stamped},
+};
+
+/// Refuses deliveries that were not signed by `sender`, were sent more than `window` seconds
+/// from now, or were already received. Accepted deliveries are remembered until they fall out of
+/// the window, whatever window later requests are checked against.
+pub fn check_replay(
+    delivery: &Delivery,
+    sender: PeerId,
+    recipient: PeerId,
+    window: u64,
+    store: &Store,
+) -> Result<()> {
+    delivery.verify(&sender, &recipient)?;
+    remember(&delivery.id, delivery.sent_at, sender, window, store)
+}
+
+/// Refuses manifests, pulls, collections, chat messages and cancellations like deliveries, within
+/// the replay window.
+pub fn check_stamp<T: Serialize>(
+    request: &Stamped<T>,
+    sender: PeerId,
+    recipient: PeerId,
+    config: &Config,
+    store: &Store,
+) -> Result<()> {
+    request.verify(&sender, &recipient)?;
+    remember(
+        &request.id,
+        request.sent_at,
+        sender,
+        config.replay_window(),
+        store,
+    )
+}
+
+fn remember(id: &[u8], sent_at: i64, sender: PeerId, window: u64, store: &Store) -> Result<()> {
+    let window = window as i64;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    if (now - sent_at).abs() > window {
+        return Err(anyhow!(
+            "Request was sent {}s from now, outside of the {window}s replay window",
+            now - sent_at
+        ));
+    }
+
+    SeenRequest::prune(now, store)?;
+    let seen = SeenRequest::new(id, sender, sent_at, sent_at + window);
+    if seen.exists(store)? {
+        return Err(anyhow!("Request {} was already received", seen.id()));
+    }
+    seen.save(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::{check_replay, check_stamp};
+    use crate::{
+        config::Config,
+        database::Store,
+        item::{Delivery, Item, ItemType, Stamped},
+    };
+
+    #[test]
+    fn replayed_requests() -> Result<()> {
+        let db_path = assert_fs::NamedTempFile::new("scs_replay.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let config: Config = serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: {:?}
+            debug: 0
+            seed: test
+            replay_window: 60
+        ",
+            db_path.parent().unwrap()
+        ))?;
+
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let items = vec![Item::new("foo,bar".to_string(), ItemType::Secret)?];
+        let delivery = Delivery::new(items.clone(), &sender, &recipient)?;
+        let sender_id = sender.public().to_peer_id();
+
+        let window = config.replay_window();
+        check_replay(&delivery, sender_id, recipient, window, &store)?;
+        let err = check_replay(&delivery, sender_id, recipient, window, &store).unwrap_err();
+        assert!(err.to_string().contains("already received"));
+
+        let other = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(check_replay(&delivery, other, recipient, window, &store).is_err());
+
+        let collect = Stamped::new((), &sender, &recipient)?;
+        check_stamp(&collect, sender_id, recipient, &config, &store)?;
+        let err = check_stamp(&collect, sender_id, recipient, &config, &store).unwrap_err();
+        assert!(err.to_string().contains("already received"));
+
+        //a deposit collected from a mailbox an hour after it was sent is still refused again
+        //after a direct request, which is checked against a shorter window
+        let deposit =
+            Delivery::new(items, &sender, &recipient)?.backdated(3600, &sender, &recipient)?;
+        let mailbox_window = config.mailbox_window();
+        check_replay(&deposit, sender_id, recipient, mailbox_window, &store)?;
+        let chat = Stamped::new((), &sender, &recipient)?;
+        check_stamp(&chat, sender_id, recipient, &config, &store)?;
+        let err = check_replay(&deposit, sender_id, recipient, mailbox_window, &store).unwrap_err();
+        assert!(err.to_string().contains("already received"));
+
+        db_path.close()?;
+        Ok(())
+    }
+}
@@ -93,7 +93,7 @@ mod tests {
             protocol_version: "test/0.0.1".to_string(),
             agent_version: "foo/bar".to_string(),
             listen_addrs: multi_addrs,
-            protocols: protocols,
+            protocols,
             observed_addr: Multiaddr::from_str("/ip4/186.0.0.2/tcp/43675").unwrap(),
         };
 
@@ -0,0 +1,215 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    config::Config,
+    database::{peer::ScsPeer, provenance::Provenance, Store},
+    item::{digest, ItemMessage, ItemType, Secret},
+    Cli,
+};
+
+/// Proves where received items came from by checking the senders' signatures again.
+/// `-s` selects secrets by key and `-f` files by name, otherwise every received item is checked.
+pub fn verify(opts: &Cli, config: &Config, store: &Store) -> Result<()> {
+    let path = config.save_path();
+    let local_peer_id = config.keypair().public().to_peer_id();
+    let mut stored = vec![];
+
+    let select_all = opts.secret.is_none() && opts.file.is_none();
+    if let Some(keys) = &opts.secret {
+        for secret in Secret::fetch_all(&path)? {
+            if keys.contains(&secret.key) || keys.contains(&secret.name()) {
+                stored.push((ItemType::Secret, secret.name(), secret.digest()));
+            }
+        }
+    }
+    if select_all {
+        for secret in Secret::fetch_all(&path)? {
+            stored.push((ItemType::Secret, secret.name(), secret.digest()));
+        }
+        for message in ItemMessage::fetch_all(&path)? {
+            let digest = digest(&ItemType::Message, "", message.msg().as_bytes());
+            stored.push((ItemType::Message, String::new(), digest));
+        }
+    }
+
+    let selected = match &opts.file {
+        Some(names) => Some(names.as_slice()),
+        None if select_all => None,
+        None => Some(&[][..]),
+    };
+    for (name, digest) in stored_files(selected, &path, store)? {
+        stored.push((ItemType::File, name, digest));
+    }
+
+    let mut failures = 0;
+    for (item_type, name, digest) in stored {
+        let label = match item_type {
+            ItemType::Message => "message".to_string(),
+            _ => format!("{} {name}", format!("{item_type:?}").to_lowercase()),
+        };
+        let record = match Provenance::get_by_digest(&digest, store)? {
+            Some(record) => record,
+            None => {
+                println!(
+                    "{label}: no record of who sent it, or it was modified after it was received"
+                );
+                failures += 1;
+                continue;
+            }
+        };
+
+        match record.verify(&local_peer_id) {
+            Ok(_) => {
+                let sender = record.sender()?;
+                let sender = match ScsPeer::get_by_peer_id(sender.to_string(), store)? {
+                    Some(peer) => format!("{} ({sender})", peer.name()),
+                    None => sender.to_string(),
+                };
+                let session = match record.session() {
+                    Some(session) => format!(", in session {}", short_session(session)),
+                    None => String::new(),
+                };
+                println!(
+                    "{label}: sent by {sender}, signed at {}{session}",
+                    record.signed_at()?
+                );
+            }
+            Err(err) => {
+                println!("{label}: {err}");
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow!("{failures} items could not be verified"));
+    }
+    Ok(())
+}
+
+/// The digests of the received files `selected` by the name they were sent or saved as, or of
+/// every received file, with the names they were saved as. Files are read from the name they were
+/// saved as, which `on_collision` or characters other platforms do not allow can change, and
+/// hashed with the one they were sent as.
+fn stored_files(
+    selected: Option<&[String]>,
+    path: &Path,
+    store: &Store,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut files = Provenance::fetch_all(store)?
+        .iter()
+        .filter(|record| record.item_type() == "File")
+        .map(|record| {
+            let saved_as = record.saved_as().unwrap_or(record.name());
+            (saved_as.to_string(), record.name().to_string())
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+    if let Some(names) = selected {
+        files.retain(|(saved_as, sent_as)| names.contains(saved_as) || names.contains(sent_as));
+        for name in names {
+            if !files
+                .iter()
+                .any(|(saved_as, sent_as)| saved_as == name || sent_as == name)
+            {
+                files.push((name.clone(), name.clone()));
+            }
+        }
+    }
+
+    let mut stored = vec![];
+    for (saved_as, sent_as) in files {
+        match fs::read(path.join(&saved_as)) {
+            Ok(data) => stored.push((saved_as, digest(&ItemType::File, &sent_as, &data))),
+            Err(_) => println!("file {saved_as}: not found"),
+        }
+    }
+    Ok(stored)
+}
+
+/// Sessions are shown, and can be picked, by the start of their id.
+pub fn short_session(session: &str) -> &str {
+    &session[..session.len().min(8)]
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use assert_fs::prelude::FileWriteStr;
+    use libp2p::identity::Keypair;
+
+    use super::stored_files;
+    use crate::{
+        config::Config,
+        database::{provenance::Provenance, Store},
+        item::{Item, ItemType},
+    };
+
+    #[test]
+    fn renamed_files() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let db_path = assert_fs::NamedTempFile::new("scs_verify.db3")?;
+        let store = Store::initialize(Some(db_path.path().to_path_buf()))?;
+        let config: Config = serde_yaml::from_str(&format!(
+            "
+            port: 5555
+            save_path: '{}'
+            debug: 0
+            seed: verify
+            on_collision: rename
+        ",
+            save_dir.path().to_str().unwrap()
+        ))?;
+        let sender = Keypair::generate_ed25519();
+        let recipient = config.keypair().public().to_peer_id();
+
+        //the second file of the same name is saved as `notes (1).txt`
+        let fixture = assert_fs::NamedTempFile::new("notes.txt")?;
+        for content in ["first", "second"] {
+            fixture.write_str(content)?;
+            let mut item = Item::new(fixture.path().to_str().unwrap().to_string(), ItemType::File)?;
+            item.seal(&recipient, true)?;
+            item.sign(&sender, &recipient)?;
+            let signature = item.verify(&sender.public().to_peer_id(), &recipient)?;
+            let saved_as = item.save(&config)?.map(|saved| saved.name);
+            Provenance::new(
+                &item,
+                sender.public().to_peer_id(),
+                signature,
+                None,
+                saved_as,
+            )
+            .save(&store)?;
+        }
+
+        let stored = stored_files(None, &config.save_path(), &store)?;
+        let names = stored
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["notes (1).txt", "notes.txt"]);
+        for (_, digest) in &stored {
+            let record = Provenance::get_by_digest(digest, &store)?.unwrap();
+            record.verify(&recipient)?;
+        }
+
+        let selected = ["notes (1).txt".to_string()];
+        assert_eq!(
+            stored_files(Some(&selected), &config.save_path(), &store)?,
+            stored[..1]
+        );
+        let selected = ["notes.txt".to_string()];
+        assert_eq!(
+            stored_files(Some(&selected), &config.save_path(), &store)?,
+            stored
+        );
+
+        fixture.close()?;
+        db_path.close()?;
+        save_dir.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//Receivers acknowledge every item they save by signing its digest, so senders can keep proof
+//of what was delivered to whom. The acknowledgement names the sender, so it can't be passed
+//off as proof of a delivery from someone else.
+
+use anyhow::{anyhow, Result};
+use libp2p::{identity::Keypair, PeerId};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::sealed::public_key;
+
+const DOMAIN: &[u8] = b"scs-receipt-v1";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Acknowledgement {
+    #[serde(with = "serde_bytes")]
+    pub digest: Vec<u8>,
+    /// Unix timestamp of when the item was saved
+    pub received_at: i64,
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl Acknowledgement {
+    pub fn sign(keypair: &Keypair, sender: &PeerId, digest: &[u8]) -> Result<Acknowledgement> {
+        let received_at = OffsetDateTime::now_utc().unix_timestamp();
+        let signature = keypair.sign(&signed_bytes(sender, digest, received_at))?;
+        Ok(Acknowledgement {
+            digest: digest.to_vec(),
+            received_at,
+            signature,
+        })
+    }
+
+    /// Checks that `receiver` acknowledged the item it got from `sender`.
+    pub fn verify(&self, receiver: &PeerId, sender: &PeerId) -> Result<()> {
+        let key = public_key(receiver)?;
+        if key.verify(
+            &signed_bytes(sender, &self.digest, self.received_at),
+            &self.signature,
+        ) {
+            Ok(())
+        } else {
+            Err(anyhow!("Acknowledgement was not signed by {receiver}"))
+        }
+    }
+}
+
+/// An item the receiver did not save or could not acknowledge, and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    #[serde(with = "serde_bytes")]
+    pub digest: Vec<u8>,
+    pub reason: String,
+}
+
+fn signed_bytes(sender: &PeerId, digest: &[u8], received_at: i64) -> Vec<u8> {
+    let mut bytes = DOMAIN.to_vec();
+    bytes.extend(sender.to_bytes());
+    bytes.extend_from_slice(digest);
+    bytes.extend_from_slice(&received_at.to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::Acknowledgement;
+    use crate::item::{digest, ItemType};
+
+    #[test]
+    fn sign_and_verify() -> Result<()> {
+        let receiver = Keypair::generate_ed25519();
+        let sender = Keypair::generate_ed25519().public().to_peer_id();
+        let digest = digest(&ItemType::Secret, "foo", b"sealed:bar");
+
+        let ack = Acknowledgement::sign(&receiver, &sender, &digest)?;
+        let receiver = receiver.public().to_peer_id();
+        ack.verify(&receiver, &sender)?;
+
+        let other = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(ack.verify(&other, &sender).is_err());
+        assert!(ack.verify(&receiver, &other).is_err());
+        Ok(())
+    }
+}
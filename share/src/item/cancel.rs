@@ -0,0 +1,91 @@
+//Either side of a transfer can call it off, for some of its items or for all of them. Items are
+//named by the digest in their manifest entry.
+
+use serde::{Deserialize, Serialize};
+
+use super::Manifest;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cancel {
+    /// Empty when the whole session is cancelled
+    pub digests: Vec<Vec<u8>>,
+    pub reason: Option<String>,
+}
+
+impl Cancel {
+    /// Cancels everything in flight with the peer.
+    pub fn all(reason: &str) -> Cancel {
+        Cancel {
+            digests: vec![],
+            reason: Some(reason.to_string()),
+        }
+    }
+
+    /// Cancels the entries of `manifest` named `name`. None if there is no such entry.
+    pub fn named(manifest: &Manifest, name: &str, reason: &str) -> Option<Cancel> {
+        let digests = manifest
+            .entries
+            .iter()
+            .filter(|entry| entry.name == name)
+            .map(|entry| entry.digest.clone())
+            .collect::<Vec<_>>();
+        (!digests.is_empty()).then(|| Cancel {
+            digests,
+            reason: Some(reason.to_string()),
+        })
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.digests.is_empty()
+    }
+
+    /// Whether the item with `digest` is cancelled.
+    pub fn covers(&self, digest: &[u8]) -> bool {
+        self.is_all() || self.digests.iter().any(|cancelled| cancelled == digest)
+    }
+
+    /// Adds what `other` cancels.
+    pub fn merge(&mut self, other: Cancel) {
+        if self.is_all() || other.is_all() {
+            self.digests.clear();
+        } else {
+            self.digests.extend(other.digests);
+        }
+        if other.reason.is_some() {
+            self.reason = other.reason;
+        }
+    }
+
+    pub fn reason(&self) -> String {
+        self.reason.clone().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::Cancel;
+    use crate::item::{Item, ItemType, Manifest};
+
+    #[test]
+    fn cancelled_items() -> Result<()> {
+        let items = vec![
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+            Item::new("baz,woo".to_string(), ItemType::Secret)?,
+        ];
+        let manifest = Manifest::new(&items);
+        assert!(Cancel::named(&manifest, "qux", "").is_none());
+
+        let mut cancel = Cancel::named(&manifest, "foo", "not needed").unwrap();
+        assert!(!cancel.is_all());
+        assert!(cancel.covers(&items[0].digest()));
+        assert!(!cancel.covers(&items[1].digest()));
+
+        cancel.merge(Cancel::all("closing"));
+        assert!(cancel.is_all());
+        assert!(cancel.covers(&items[1].digest()));
+        assert_eq!(cancel.reason(), "closing");
+        Ok(())
+    }
+}
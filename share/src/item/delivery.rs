@@ -0,0 +1,112 @@
+//Items are delivered in a signed batch with a random id and the time it was sent, so a receiver
+//can refuse a batch it has already saved or one that was captured and re-sent later on.
+
+use anyhow::{anyhow, Result};
+use libp2p::{identity::Keypair, PeerId};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::{sealed::public_key, Item};
+
+const DOMAIN: &[u8] = b"scs-delivery-v1";
+const ID_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    #[serde(with = "serde_bytes")]
+    pub id: Vec<u8>,
+    /// Unix timestamp of when the items were sent
+    pub sent_at: i64,
+    pub items: Vec<Item>,
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl Delivery {
+    pub fn new(items: Vec<Item>, keypair: &Keypair, recipient: &PeerId) -> Result<Delivery> {
+        let mut id = vec![0u8; ID_LEN];
+        rand::thread_rng().fill_bytes(&mut id);
+        let sent_at = OffsetDateTime::now_utc().unix_timestamp();
+        let signature = keypair.sign(&signed_bytes(recipient, &id, sent_at, &items))?;
+        Ok(Delivery {
+            id,
+            sent_at,
+            items,
+            signature,
+        })
+    }
+
+    /// The same delivery, signed again as if it was sent `seconds` earlier.
+    #[cfg(test)]
+    pub fn backdated(
+        mut self,
+        seconds: i64,
+        keypair: &Keypair,
+        recipient: &PeerId,
+    ) -> Result<Delivery> {
+        self.sent_at -= seconds;
+        self.signature = keypair.sign(&signed_bytes(
+            recipient,
+            &self.id,
+            self.sent_at,
+            &self.items,
+        ))?;
+        Ok(self)
+    }
+
+    /// Checks that `sender` sent these items, with this id and time, to `recipient`.
+    pub fn verify(&self, sender: &PeerId, recipient: &PeerId) -> Result<()> {
+        let key = public_key(sender)?;
+        let bytes = signed_bytes(recipient, &self.id, self.sent_at, &self.items);
+        if self.id.len() == ID_LEN && key.verify(&bytes, &self.signature) {
+            Ok(())
+        } else {
+            Err(anyhow!("Request was not signed by the sender {sender}"))
+        }
+    }
+}
+
+fn signed_bytes(recipient: &PeerId, id: &[u8], sent_at: i64, items: &[Item]) -> Vec<u8> {
+    let mut bytes = DOMAIN.to_vec();
+    bytes.extend(recipient.to_bytes());
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&sent_at.to_be_bytes());
+    for item in items {
+        bytes.extend(item.digest());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::Delivery;
+    use crate::item::{Item, ItemType};
+
+    #[test]
+    fn sign_and_verify() -> Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let items = vec![Item::new("foo,bar".to_string(), ItemType::Secret)?];
+
+        let delivery = Delivery::new(items.clone(), &sender, &recipient)?;
+        let sender = sender.public().to_peer_id();
+        delivery.verify(&sender, &recipient)?;
+        assert_ne!(
+            Delivery::new(items, &Keypair::generate_ed25519(), &recipient)?.id,
+            delivery.id
+        );
+
+        let mut replayed = delivery.clone();
+        replayed.id = vec![0u8; 16];
+        assert!(replayed.verify(&sender, &recipient).is_err());
+
+        let mut backdated = delivery;
+        backdated.sent_at -= 3600;
+        assert!(backdated.verify(&sender, &recipient).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+//Deliveries left on a mailbox node for a recipient that is offline. The items are sealed and signed
+//for the recipient as in a direct delivery, so the mailbox can neither read nor alter them.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use super::{Delivery, Manifest};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deposit {
+    pub sender: String,
+    pub recipient: String,
+    pub delivery: Delivery,
+}
+
+impl Deposit {
+    pub fn new(sender: PeerId, recipient: PeerId, delivery: Delivery) -> Deposit {
+        Deposit {
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            delivery,
+        }
+    }
+
+    pub fn sender(&self) -> Result<PeerId> {
+        PeerId::from_str(&self.sender).map_err(|err| anyhow!("{}", err.to_string()))
+    }
+
+    pub fn recipient(&self) -> Result<PeerId> {
+        PeerId::from_str(&self.recipient).map_err(|err| anyhow!("{}", err.to_string()))
+    }
+
+    pub fn manifest(&self) -> Manifest {
+        Manifest::new(&self.delivery.items)
+    }
+}
+
+/// The deliveries collected from a mailbox, and why they could not be if that failed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Collection {
+    pub deposits: Vec<Deposit>,
+    pub err: Option<String>,
+}
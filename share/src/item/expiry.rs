@@ -0,0 +1,122 @@
+//Senders can make a secret temporary: it expires some time after it is received, or once it has
+//been read. The receiver counts the time from when it saves the secret, so clocks need not agree.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Expiry {
+    /// Seconds the secret can be read for once it is received
+    pub after: Option<u64>,
+    /// Whether the secret is deleted once it has been read
+    #[serde(default)]
+    pub burn: bool,
+    /// Unix timestamp of when the secret expires, set by the receiver when it saves it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<i64>,
+}
+
+impl Expiry {
+    /// None when the secret is kept until it is deleted.
+    pub fn new(after: Option<u64>, burn: bool) -> Option<Expiry> {
+        (after.is_some() || burn).then_some(Expiry {
+            after,
+            burn,
+            at: None,
+        })
+    }
+
+    /// Starts the countdown from `now`, whatever the sender set.
+    pub fn start(&mut self, now: i64) {
+        self.at = self
+            .after
+            .map(|after| now.saturating_add(i64::try_from(after).unwrap_or(i64::MAX)));
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.at.is_some_and(|at| at <= now)
+    }
+
+    /// What the sender asked for, as it is signed.
+    pub fn label(&self) -> String {
+        let mut parts = vec![];
+        if let Some(after) = self.after {
+            parts.push(format!("expires in {}", format_duration(after)));
+        }
+        if self.burn {
+            parts.push("burn after reading".to_string());
+        }
+        parts.join(", ")
+    }
+}
+
+/// Parses a number of seconds or a duration such as "90s", "15m", "12h" or "7d".
+pub fn parse_duration(duration: &str) -> Result<u64> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration {duration}"))?;
+    let unit = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(anyhow!("Unknown unit in {duration}. Use s, m, h or d")),
+    };
+    number
+        .checked_mul(unit)
+        .filter(|seconds| *seconds > 0)
+        .ok_or_else(|| anyhow!("Invalid duration {duration}"))
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    for (unit, length) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60)] {
+        if seconds % length == 0 {
+            return format!("{}{unit}", seconds / length);
+        }
+    }
+    format!("{seconds}s")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{format_duration, parse_duration, Expiry};
+
+    #[test]
+    fn durations() -> Result<()> {
+        assert_eq!(parse_duration("90")?, 90);
+        assert_eq!(parse_duration("15m")?, 900);
+        assert_eq!(parse_duration(" 2 h")?, 7200);
+        assert_eq!(parse_duration("7d")?, 604_800);
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("h").is_err());
+        assert_eq!(format_duration(7200), "2h");
+        assert_eq!(format_duration(90), "90s");
+        Ok(())
+    }
+
+    #[test]
+    fn expiry() {
+        assert_eq!(Expiry::new(None, false), None);
+
+        let mut expiry = Expiry::new(Some(3600), true).unwrap();
+        assert_eq!(expiry.label(), "expires in 1h, burn after reading");
+        assert!(!expiry.is_expired(i64::MAX));
+
+        expiry.at = Some(0);
+        expiry.start(1_000);
+        assert!(!expiry.is_expired(4_599));
+        assert!(expiry.is_expired(4_600));
+
+        let mut burn = Expiry::new(None, true).unwrap();
+        burn.start(1_000);
+        assert!(!burn.is_expired(i64::MAX));
+    }
+}
@@ -0,0 +1,339 @@
+//Secrets can be read from the files they are usually kept in: dotenv files, flat JSON or YAML maps
+//and Kubernetes `Secret` manifests, whose `data` is base64.
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use super::{secret::take_quoted, Secret};
+use crate::config::{Import, ImportFormat};
+
+/// The secrets of the file `import` names that it selects.
+pub fn import_secrets(import: &Import) -> Result<Vec<Secret>> {
+    let path = &import.path;
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+    let format = match import.format {
+        Some(format) => format,
+        None => guess_format(path, &contents),
+    };
+    let secrets = match format {
+        ImportFormat::Dotenv => parse_dotenv(&contents),
+        ImportFormat::Json => serde_json::from_str::<serde_json::Value>(&contents)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(serde_yaml::to_value(json)?))
+            .and_then(|value| parse_map(&value)),
+        ImportFormat::Yaml => serde_yaml::from_str(&contents)
+            .map_err(anyhow::Error::from)
+            .and_then(|value| parse_map(&value)),
+        ImportFormat::Kubernetes => parse_kubernetes(&contents),
+    }
+    .with_context(|| format!("Failed to import {path}"))?;
+
+    for key in import.only.iter().flatten() {
+        if !secrets.iter().any(|secret| &secret.key == key) {
+            return Err(anyhow!("{path} has no secret {key}"));
+        }
+    }
+    let secrets = secrets
+        .into_iter()
+        .filter(|secret| import.selects(&secret.key))
+        .collect::<Vec<_>>();
+    if secrets.is_empty() {
+        return Err(anyhow!("No secrets to send in {path}"));
+    }
+    Ok(secrets)
+}
+
+/// JSON and YAML files are told apart by their extension, and Kubernetes manifests by their `kind`.
+/// Anything else is taken as a dotenv file.
+fn guess_format(path: &str, contents: &str) -> ImportFormat {
+    let extension = Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let format = match extension.as_deref() {
+        Some("json") => ImportFormat::Json,
+        Some("yaml" | "yml") => ImportFormat::Yaml,
+        _ => return ImportFormat::Dotenv,
+    };
+    match serde_yaml::from_str::<Value>(contents) {
+        Ok(value) if is_kubernetes_secret(&value) => ImportFormat::Kubernetes,
+        _ => match contents.trim_start().starts_with("apiVersion:") {
+            //several documents
+            true => ImportFormat::Kubernetes,
+            false => format,
+        },
+    }
+}
+
+fn is_kubernetes_secret(value: &Value) -> bool {
+    value.get("apiVersion").is_some() && value.get("kind").and_then(Value::as_str) == Some("Secret")
+}
+
+/// Adds `secret`, replacing an earlier one with its key.
+fn insert(secrets: &mut Vec<Secret>, secret: Secret) {
+    match secrets.iter_mut().find(|saved| saved.key == secret.key) {
+        Some(saved) => *saved = secret,
+        None => secrets.push(secret),
+    }
+}
+
+/// Parses `KEY=VALUE` lines, which may start with `export`. Blank lines and lines starting with
+/// `#` are skipped, as is a `#` comment after an unquoted value. Quoted values are read as they
+/// are with `-s` and may span several lines.
+fn parse_dotenv(contents: &str) -> Result<Vec<Secret>> {
+    let mut secrets = vec![];
+    let mut lines = contents.lines().enumerate();
+    while let Some((no, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim_start()),
+            None => return Err(anyhow!("Line {} is not KEY=VALUE", no + 1)),
+        };
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(anyhow!("Line {} has no valid key", no + 1));
+        }
+
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let mut quoted = value.to_string();
+                loop {
+                    let invalid = || format!("Invalid value for the secret {key}");
+                    if let Some((value, rest)) =
+                        take_quoted(&quoted, quote).with_context(invalid)?
+                    {
+                        let rest = rest.trim();
+                        if !rest.is_empty() && !rest.starts_with('#') {
+                            return Err(anyhow!("Nothing may follow the closing {quote} of {key}"));
+                        }
+                        break value;
+                    }
+                    match lines.next() {
+                        Some((_, next)) => {
+                            quoted.push('\n');
+                            quoted.push_str(next);
+                        }
+                        None => return Err(anyhow!("The closing {quote} of {key} is missing")),
+                    }
+                }
+            }
+            _ => strip_comment(value).to_string(),
+        };
+        insert(&mut secrets, Secret::new(key.to_string(), value));
+    }
+    Ok(secrets)
+}
+
+fn strip_comment(value: &str) -> &str {
+    let comment = value
+        .char_indices()
+        .find(|(at, c)| *c == '#' && value[..*at].ends_with(char::is_whitespace));
+    match comment {
+        Some((at, _)) => value[..at].trim_end(),
+        None => value.trim_end(),
+    }
+}
+
+/// Reads a map of keys to strings, numbers or booleans.
+fn parse_map(value: &Value) -> Result<Vec<Secret>> {
+    let map = value
+        .as_mapping()
+        .ok_or_else(|| anyhow!("Only a map of keys to values can be imported"))?;
+    let mut secrets = vec![];
+    for (key, value) in map {
+        let key = scalar(key).ok_or_else(|| anyhow!("Keys must be strings"))?;
+        let value = scalar(value).ok_or_else(|| {
+            anyhow!("The value of {key} is not a string; only flat maps can be imported")
+        })?;
+        insert(&mut secrets, Secret::new(key, value));
+    }
+    Ok(secrets)
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Reads the `data` and `stringData` of every `Secret` in the manifest, which may hold several
+/// documents or a `List`. As in Kubernetes, `stringData` wins over `data`.
+fn parse_kubernetes(contents: &str) -> Result<Vec<Secret>> {
+    let mut manifests = vec![];
+    for document in serde_yaml::Deserializer::from_str(contents) {
+        let value = Value::deserialize(document)?;
+        match value.get("kind").and_then(Value::as_str) {
+            Some("List") => {
+                let items = value.get("items").and_then(Value::as_sequence);
+                manifests.extend(items.into_iter().flatten().cloned());
+            }
+            _ => manifests.push(value),
+        }
+    }
+
+    let mut secrets = vec![];
+    let mut found = false;
+    for manifest in manifests.iter().filter(|value| is_kubernetes_secret(value)) {
+        found = true;
+        let name = manifest
+            .get("metadata")
+            .and_then(|metadata| metadata.get("name"))
+            .and_then(Value::as_str)
+            .unwrap_or("Secret");
+        if let Some(data) = manifest.get("data").and_then(Value::as_mapping) {
+            for secret in decode_data(data).with_context(|| format!("Invalid data in {name}"))? {
+                insert(&mut secrets, secret);
+            }
+        }
+        if let Some(data) = manifest.get("stringData") {
+            for secret in
+                parse_map(data).with_context(|| format!("Invalid stringData in {name}"))?
+            {
+                insert(&mut secrets, secret);
+            }
+        }
+    }
+    if !found {
+        return Err(anyhow!("There is no Kubernetes Secret in it"));
+    }
+    Ok(secrets)
+}
+
+fn decode_data(data: &Mapping) -> Result<Vec<Secret>> {
+    data.iter()
+        .map(|(key, value)| {
+            let key = key
+                .as_str()
+                .ok_or_else(|| anyhow!("Keys must be strings"))?;
+            let value = value.as_str().unwrap_or_default();
+            let decoded = STANDARD
+                .decode(value.trim())
+                .with_context(|| format!("The value of {key} is not valid base64"))?;
+            let value = String::from_utf8(decoded)
+                .map_err(|_| anyhow!("The value of {key} is not text"))?;
+            Ok(Secret::new(key.to_string(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use assert_fs::prelude::FileWriteStr;
+
+    use super::{import_secrets, parse_dotenv, parse_kubernetes, parse_map};
+    use crate::{
+        config::{Import, ImportFormat},
+        item::Secret,
+    };
+
+    fn secret(key: &str, value: &str) -> Secret {
+        Secret::new(key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn dotenv_files() -> Result<()> {
+        let contents = "# database\nexport DATABASE_URL=postgres://db:5432 # local\n\nTOKEN=\"a#b\\n\" # kept\nEMPTY=\nKEY='-----BEGIN KEY-----\nabc\n-----END KEY-----'\nTOKEN=c#d\n";
+        assert_eq!(
+            parse_dotenv(contents)?,
+            vec![
+                secret("DATABASE_URL", "postgres://db:5432"),
+                secret("TOKEN", "c#d"),
+                secret("EMPTY", ""),
+                secret("KEY", "-----BEGIN KEY-----\nabc\n-----END KEY-----"),
+            ]
+        );
+
+        assert!(parse_dotenv("NO_VALUE").is_err());
+        assert!(parse_dotenv("KEY=\"open\nstill open").is_err());
+        assert!(parse_dotenv("KEY='done' trailing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn flat_maps() -> Result<()> {
+        let value = serde_yaml::from_str("API_KEY: abc\nPORT: 8080\nDEBUG: false\n")?;
+        assert_eq!(
+            parse_map(&value)?,
+            vec![
+                secret("API_KEY", "abc"),
+                secret("PORT", "8080"),
+                secret("DEBUG", "false")
+            ]
+        );
+
+        let nested = serde_yaml::from_str("database:\n  url: postgres://db\n")?;
+        assert!(parse_map(&nested).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn kubernetes_secrets() -> Result<()> {
+        let manifest = "apiVersion: v1\nkind: Secret\nmetadata:\n  name: db\ndata:\n  USER: YWRtaW4=\n  PASSWORD: aHVudGVyMg==\nstringData:\n  USER: root\n---\napiVersion: v1\nkind: ConfigMap\ndata:\n  MODE: cHJvZA==\n";
+        assert_eq!(
+            parse_kubernetes(manifest)?,
+            vec![secret("USER", "root"), secret("PASSWORD", "hunter2")]
+        );
+
+        assert!(parse_kubernetes("apiVersion: v1\nkind: Secret\ndata:\n  USER: '!!'\n").is_err());
+        assert!(parse_kubernetes("apiVersion: v1\nkind: ConfigMap\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn selected_keys() -> Result<()> {
+        let file = assert_fs::NamedTempFile::new("scs_import.json")?;
+        file.write_str(r#"{"API_KEY": "abc", "DATABASE_URL": "postgres://db", "PORT": 8080}"#)?;
+        let path = file.path().to_string_lossy().to_string();
+
+        let import = Import {
+            except: Some(vec!["PORT".to_string()]),
+            ..Import::new(&path)
+        };
+        assert_eq!(
+            import_secrets(&import)?,
+            vec![
+                secret("API_KEY", "abc"),
+                secret("DATABASE_URL", "postgres://db")
+            ]
+        );
+
+        let import = Import {
+            only: Some(vec!["PORT".to_string()]),
+            except: None,
+            ..import
+        };
+        assert_eq!(import_secrets(&import)?, vec![secret("PORT", "8080")]);
+
+        let import = Import {
+            except: Some(vec!["PORT".to_string()]),
+            ..import
+        };
+        assert!(import_secrets(&import).is_err());
+
+        let import = Import {
+            only: Some(vec!["MISSING".to_string()]),
+            except: None,
+            ..import
+        };
+        assert!(import_secrets(&import).is_err());
+
+        let import = Import {
+            format: Some(ImportFormat::Dotenv),
+            only: None,
+            ..import
+        };
+        assert!(import_secrets(&import).is_err());
+        Ok(())
+    }
+}
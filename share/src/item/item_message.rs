@@ -1,8 +1,16 @@
-use std::{fs::OpenOptions, io::Write, path::Path};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    str::FromStr,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use libp2p::{identity::Keypair, PeerId};
 use serde::{Deserialize, Serialize};
 
+use super::sealed::Sealed;
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct ItemMessage {
     msg: String,
@@ -30,6 +38,38 @@ impl ItemMessage {
         file.write_all(message.as_bytes())?;
         Ok(())
     }
+
+    pub fn fetch_all(path: &Path) -> Result<Vec<ItemMessage>> {
+        let message_file_path = path.join("messages.txt");
+        if !message_file_path.exists() {
+            return Ok(vec![]);
+        }
+        let messages = fs::read_to_string(message_file_path)?
+            .lines()
+            .map(|msg| ItemMessage::new(msg.to_string()))
+            .collect();
+        Ok(messages)
+    }
+
+    pub fn seal(&mut self, recipient: &PeerId, compress: bool) -> Result<()> {
+        self.msg = Sealed::seal(recipient, self.msg.as_bytes(), compress)?.to_string();
+        Ok(())
+    }
+
+    /// Decrypts a saved message. Messages are saved sealed, so only the ones saved before items
+    /// were sealed lack the `sealed:` prefix.
+    pub fn open(&self, keypair: &Keypair, limit: u64) -> Result<ItemMessage> {
+        if !Sealed::is_sealed_str(&self.msg) {
+            return Ok(self.clone());
+        }
+        let msg = Sealed::from_str(&self.msg)?.open(keypair, limit)?;
+        let msg = String::from_utf8(msg).context("Message is not valid UTF-8")?;
+        Ok(ItemMessage { msg })
+    }
+
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
 }
 
 #[cfg(test)]
@@ -37,6 +77,7 @@ mod tests {
     use super::ItemMessage;
     use anyhow::Result;
     use assert_fs::prelude::{PathAssert, PathChild};
+    use libp2p::identity::Keypair;
     use predicates::prelude::*;
 
     #[test]
@@ -63,4 +104,24 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn seal_message() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let dir = assert_fs::TempDir::new()?;
+
+        let mut item = ItemMessage::new("first line\nsecond line".into());
+        item.seal(&keypair.public().to_peer_id(), true)?;
+        item.save(dir.path())?;
+
+        let messages = ItemMessage::fetch_all(dir.path())?;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].open(&keypair, u64::MAX)?.msg,
+            "first line\nsecond line"
+        );
+
+        dir.close()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Item, ItemType};
+
+/// What a sender wants to deliver, shown to the receiver before any payload is sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub item_type: ItemType,
+    pub name: String,
+    pub size: u64,
+    #[serde(with = "serde_bytes")]
+    pub digest: Vec<u8>,
+    /// Offered for files, so the receiver can tell whether it already has them
+    #[serde(default, with = "serde_bytes")]
+    pub content_hash: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestResponse {
+    pub accepted: bool,
+    pub reason: Option<String>,
+    /// Digests of the entries the receiver already has, which need not be sent
+    #[serde(default)]
+    pub held: Vec<Vec<u8>>,
+}
+
+impl Manifest {
+    pub fn new(items: &[Item]) -> Manifest {
+        let entries = items
+            .iter()
+            .map(|item| ManifestEntry {
+                item_type: item.item_type(),
+                name: item.name(),
+                size: item.payload().len() as u64,
+                digest: item.digest(),
+                content_hash: item.content_hash(),
+            })
+            .collect();
+        Manifest { entries }
+    }
+
+    /// Whether `items` are the ones described by the manifest. Content hashes are only known
+    /// to the sender, so they are not compared.
+    pub fn matches(&self, items: &[Item]) -> bool {
+        let other = Manifest::new(items);
+        self.entries.len() == other.entries.len()
+            && self
+                .entries
+                .iter()
+                .zip(&other.entries)
+                .all(|(entry, other)| {
+                    (&entry.item_type, &entry.name, entry.size, &entry.digest)
+                        == (&other.item_type, &other.name, other.size, &other.digest)
+                })
+    }
+
+    /// The manifest without the entries whose digests are in `digests`.
+    pub fn without(&self, digests: &[Vec<u8>]) -> Manifest {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|entry| !digests.contains(&entry.digest))
+            .cloned()
+            .collect();
+        Manifest { entries }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+
+    pub fn count(&self, item_type: ItemType) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.item_type == item_type)
+            .count()
+    }
+}
+
+impl ManifestResponse {
+    pub fn accepted() -> ManifestResponse {
+        ManifestResponse {
+            accepted: true,
+            reason: None,
+            held: vec![],
+        }
+    }
+
+    pub fn declined(reason: &str) -> ManifestResponse {
+        ManifestResponse {
+            accepted: false,
+            reason: Some(reason.to_string()),
+            held: vec![],
+        }
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = [
+            (self.count(ItemType::Secret), "secret"),
+            (self.count(ItemType::Message), "message"),
+            (self.count(ItemType::File), "file"),
+        ];
+        let summary = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, name)| match count {
+                1 => format!("1 {name}"),
+                _ => format!("{count} {name}s"),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{} ({})", summary.join(", "), format_size(self.size()))
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next;
+    }
+    format!("{size:.1} {unit}")
+}
+
+/// Parses sizes such as `512`, `40 MB` or `1.5GB`. Units are powers of 1000, as in [`format_size`].
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| anyhow!("Invalid size {size:?}"))?;
+    let multiplier = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1e0,
+        "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        _ => {
+            return Err(anyhow!(
+                "Invalid size {size:?}. Expected a unit of B, KB, MB, GB or TB"
+            ))
+        }
+    };
+    Ok((number * multiplier) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{format_size, parse_size, Manifest};
+    use crate::item::{Item, ItemType};
+
+    #[test]
+    fn summary() -> Result<()> {
+        let items = vec![
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+            Item::new("baz,woo".to_string(), ItemType::Secret)?,
+            Item::new("hi there".to_string(), ItemType::Message)?,
+        ];
+        let manifest = Manifest::new(&items);
+        assert_eq!(manifest.to_string(), "2 secrets, 1 message (14 B)");
+        assert_eq!(manifest.entries[0].name, "foo");
+        Ok(())
+    }
+
+    #[test]
+    fn matching_items() -> Result<()> {
+        let items = vec![Item::new("foo,bar".to_string(), ItemType::Secret)?];
+        let manifest = Manifest::new(&items);
+        assert!(manifest.matches(&items));
+
+        let other = vec![Item::new("foo,barbaz".to_string(), ItemType::Secret)?];
+        assert!(!manifest.matches(&other));
+
+        let mut items = vec![
+            Item::new("baz,woo".to_string(), ItemType::Secret)?,
+            items[0].clone(),
+        ];
+        let manifest = Manifest::new(&items);
+        let held = manifest.without(&[items[0].digest()]);
+        items.remove(0);
+        assert!(held.matches(&items));
+        Ok(())
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(999), "999 B");
+        assert_eq!(format_size(40_000_000), "40.0 MB");
+        assert_eq!(format_size(1_500), "1.5 KB");
+    }
+
+    #[test]
+    fn parsed_sizes() -> Result<()> {
+        assert_eq!(parse_size("512")?, 512);
+        assert_eq!(parse_size("40 MB")?, 40_000_000);
+        assert_eq!(parse_size("1.5gb")?, 1_500_000_000);
+        assert!(parse_size("40 MiB").is_err());
+        assert!(parse_size("lots").is_err());
+        Ok(())
+    }
+}
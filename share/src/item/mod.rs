@@ -1,27 +1,119 @@
-use std::ffi::OsString;
+use std::{ffi::OsString, path::Path, str::FromStr};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use libp2p::{identity::Keypair, PeerId};
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
-
+use crate::config::{Config, Directories};
+use sealed::Sealed;
+
+pub use acknowledgement::{Acknowledgement, Failure};
+pub use cancel::Cancel;
+pub use delivery::Delivery;
+pub use deposit::{Collection, Deposit};
+pub use expiry::{parse_duration, Expiry};
+pub use import::import_secrets;
+pub use item_file::{ItemFile, SavedFile};
+pub use item_message::ItemMessage;
+pub use manifest::{format_size, parse_size, Manifest, ManifestEntry, ManifestResponse};
+pub use namespace::Namespace;
+pub use signature::{digest, ItemSignature};
+pub use stamped::Stamped;
+pub use transcript::Transcript;
+pub use version::Version;
+
+mod acknowledgement;
+mod cancel;
+mod delivery;
+mod deposit;
+mod expiry;
+mod import;
 mod item_file;
 mod item_message;
+mod manifest;
+mod namespace;
+mod sealed;
 mod secret;
+mod signature;
+mod stamped;
+mod transcript;
+mod version;
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Secret {
     pub key: String,
     pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<Expiry>,
+    /// Set by the receiver when it saves the secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    /// The earlier values of a saved secret, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Secret>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ItemFields")]
 //TODO implemnt fmt::Display for all
 pub struct Item {
     item_type: ItemType,
     message: Option<item_message::ItemMessage>,
     secret: Option<Secret>,
     file: Option<item_file::ItemFile>,
+    signature: Option<ItemSignature>,
+    /// Set when the payload is sealed, rather than guessed from what it starts with
+    #[serde(default)]
+    sealed: bool,
+}
+
+/// The fields of an item as a peer sent them, before checking that the payload matches the type.
+#[derive(Deserialize)]
+struct ItemFields {
+    item_type: ItemType,
+    message: Option<item_message::ItemMessage>,
+    secret: Option<Secret>,
+    file: Option<item_file::ItemFile>,
+    signature: Option<ItemSignature>,
+    #[serde(default)]
+    sealed: bool,
+}
+
+impl TryFrom<ItemFields> for Item {
+    type Error = anyhow::Error;
+
+    /// An item holds the payload of its type and no other, so the accessors can rely on it.
+    fn try_from(fields: ItemFields) -> Result<Self> {
+        let held = (
+            fields.file.is_some(),
+            fields.message.is_some(),
+            fields.secret.is_some(),
+        );
+        let expected = match fields.item_type {
+            ItemType::File => (true, false, false),
+            ItemType::Message => (false, true, false),
+            ItemType::Secret => (false, false, true),
+        };
+        if held != expected {
+            return Err(anyhow!(
+                "{:?} item does not hold a {:?} alone",
+                fields.item_type,
+                fields.item_type
+            ));
+        }
+        Ok(Item {
+            item_type: fields.item_type,
+            message: fields.message,
+            secret: fields.secret,
+            file: fields.file,
+            signature: fields.signature,
+            sealed: fields.sealed,
+        })
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -36,7 +128,16 @@ pub struct ItemResponse {
     pub status: Status,
     pub no_of_success: usize,
     pub no_of_fails: usize,
+    /// Items dropped because either side cancelled them
+    #[serde(default)]
+    pub no_of_cancelled: usize,
     pub err: Option<String>,
+    /// Signed by the receiver for every item it saved
+    #[serde(default)]
+    pub acks: Vec<Acknowledgement>,
+    /// The items the receiver failed to save or to acknowledge
+    #[serde(default)]
+    pub failed: Vec<Failure>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -55,6 +156,8 @@ impl Item {
                     secret: Some(secret),
                     message: None,
                     file: None,
+                    signature: None,
+                    sealed: false,
                 }
             }
             ItemType::File => {
@@ -64,6 +167,8 @@ impl Item {
                     secret: None,
                     message: None,
                     file: Some(file),
+                    signature: None,
+                    sealed: false,
                 }
             }
             ItemType::Message => Item {
@@ -71,25 +176,168 @@ impl Item {
                 message: Some(item_message::ItemMessage::new(param)),
                 secret: None,
                 file: None,
+                signature: None,
+                sealed: false,
             },
         };
 
         Ok(item)
     }
 
-    pub fn save(&self, config: &Config) -> Result<()> {
+    /// The file at `path`, or every file of the directory at `path` that `directories` lets through.
+    pub fn files(path: &str, directories: &Directories) -> Result<Vec<Self>> {
+        if !Path::new(path).is_dir() {
+            return Ok(vec![Item::new(path.to_string(), ItemType::File)?]);
+        }
+        let files = ItemFile::from_dir(Path::new(path), directories)?
+            .into_iter()
+            .map(|file| Item {
+                item_type: ItemType::File,
+                secret: None,
+                message: None,
+                file: Some(file),
+                signature: None,
+                sealed: false,
+            })
+            .collect();
+        Ok(files)
+    }
+
+    /// Saves the item under the configured `save_path`, returning the name a file was saved as.
+    /// Items are always stored sealed: sealed ones must open with our key, and ones made here are
+    /// sealed to it first.
+    pub fn save(&self, config: &Config) -> Result<Option<SavedFile>> {
         let path = &config.save_path();
+        let keypair = config.keypair();
+        let mut item = self.clone();
+        if item.sealed {
+            item.open(&keypair, config.max_opened_size(), config.compression())?;
+        } else {
+            item.seal(&keypair.public().to_peer_id(), config.compression())?;
+        }
+        match item.item_type {
+            ItemType::File => {
+                let saved = item.file.unwrap().save(path, config.on_collision())?;
+                return Ok(Some(saved));
+            }
+            ItemType::Message => item.message.unwrap().save(path)?,
+            ItemType::Secret => item
+                .secret
+                .unwrap()
+                .save_secret(path, config.secret_history())?,
+        }
+        Ok(None)
+    }
+
+    /// Encrypts the payload of the item to the key of `recipient`. Items are sealed only once.
+    pub fn seal(&mut self, recipient: &PeerId, compress: bool) -> Result<()> {
+        if self.sealed {
+            return Err(anyhow!("{:?} is already sealed", self.item_type));
+        }
         match self.item_type {
-            ItemType::File => self.file.clone().unwrap().save(path)?,
-            ItemType::Message => self.message.clone().unwrap().save(path)?,
-            ItemType::Secret => self.secret.clone().unwrap().save_secret(path)?,
+            ItemType::File => self.file.as_mut().unwrap().seal(recipient, compress)?,
+            ItemType::Message => self.message.as_mut().unwrap().seal(recipient, compress)?,
+            ItemType::Secret => self.secret.as_mut().unwrap().seal(recipient, compress)?,
         }
+        self.sealed = true;
         Ok(())
     }
 
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    /// Decrypts the payload of a sealed item with `keypair`. Payloads that hold more than `limit`
+    /// bytes, or that were compressed while `compression` is off, are refused.
+    pub fn open(&self, keypair: &Keypair, limit: u64, compression: bool) -> Result<Vec<u8>> {
+        if !self.sealed {
+            return Err(anyhow!("{:?} is not sealed", self.item_type));
+        }
+        let sealed = match self.item_type {
+            ItemType::File => Sealed::from_bytes(self.payload())?,
+            ItemType::Message | ItemType::Secret => {
+                Sealed::from_str(std::str::from_utf8(self.payload())?)?
+            }
+        };
+        sealed.open_received(keypair, limit, compression)
+    }
+
     pub fn item_type(&self) -> ItemType {
         self.item_type.clone()
     }
+
+    /// The key of a secret, with its namespace, or the name of a file. Messages have no name.
+    pub fn name(&self) -> String {
+        match self.item_type {
+            ItemType::File => self.file.as_ref().unwrap().name(),
+            ItemType::Message => String::new(),
+            ItemType::Secret => self.secret.as_ref().unwrap().name(),
+        }
+    }
+
+    /// Puts a secret in `namespace`, unless it already is in one. Other items have no namespace.
+    pub fn set_namespace(&mut self, namespace: &Namespace) -> Result<()> {
+        match self.secret.as_mut() {
+            Some(secret) => {
+                namespace.apply(secret);
+                Namespace::of(secret).check()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Makes a secret temporary, unless the sender already gave it an expiry.
+    pub fn set_expiry(&mut self, expiry: Option<&Expiry>) {
+        if let Some(secret) = self.secret.as_mut() {
+            if secret.expiry.is_none() {
+                secret.expiry = expiry.cloned();
+            }
+        }
+    }
+
+    /// Records who sent a secret, which is saved with its value.
+    pub fn set_sender(&mut self, sender: &PeerId) {
+        if let Some(secret) = self.secret.as_mut() {
+            secret.version = Some(Version::sent_by(sender));
+        }
+    }
+
+    /// The payload of the item as it is saved.
+    pub fn payload(&self) -> &[u8] {
+        match self.item_type {
+            ItemType::File => self.file.as_ref().unwrap().data(),
+            ItemType::Message => self.message.as_ref().unwrap().msg().as_bytes(),
+            ItemType::Secret => self.secret.as_ref().unwrap().value.as_bytes(),
+        }
+    }
+
+    /// Hash of the content of a file, kept by the sender once the file is sealed.
+    pub fn content_hash(&self) -> Option<Vec<u8>> {
+        self.file.as_ref().and_then(ItemFile::content_hash)
+    }
+
+    pub fn digest(&self) -> Vec<u8> {
+        match &self.secret {
+            Some(secret) => secret.digest(),
+            None => digest(&self.item_type, &self.name(), self.payload()),
+        }
+    }
+
+    /// Signs the item for `recipient`. Items must be sealed before they are signed.
+    pub fn sign(&mut self, keypair: &Keypair, recipient: &PeerId) -> Result<()> {
+        self.signature = Some(ItemSignature::sign(keypair, recipient, &self.digest())?);
+        Ok(())
+    }
+
+    /// Checks that the item was signed by `sender` for `recipient`.
+    pub fn verify(&self, sender: &PeerId, recipient: &PeerId) -> Result<&ItemSignature> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| anyhow!("{:?} is not signed by the sender", self.item_type))?;
+        signature.verify(sender, recipient, &self.digest())?;
+        Ok(signature)
+    }
 }
 
 impl From<Secret> for Item {
@@ -99,6 +347,8 @@ impl From<Secret> for Item {
             secret: Some(secret),
             message: None,
             file: None,
+            signature: None,
+            sealed: false,
         }
     }
 }
@@ -110,6 +360,8 @@ impl From<&Secret> for Item {
             secret: Some(secret.clone()),
             message: None,
             file: None,
+            signature: None,
+            sealed: false,
         }
     }
 }
@@ -118,9 +370,10 @@ impl From<&Secret> for Item {
 mod tests {
     use crate::config::Config;
 
-    use super::{Item, ItemType};
+    use super::{Expiry, Item, ItemFile, ItemMessage, ItemType, Secret};
     use anyhow::Result;
     use assert_fs::prelude::{FileWriteStr, PathAssert, PathChild};
+    use libp2p::identity::Keypair;
     use predicates::prelude::*;
 
     fn make_config(path: &str) -> Result<Config> {
@@ -163,9 +416,11 @@ mod tests {
 
         let secret_file = save_dir.child("secrets.json");
         secret_file.assert(predicate::path::exists());
+        secret_file.assert(predicate::str::contains(r#"[{"key":"hi","value":"sealed:"#));
 
-        let secrets = r#"[{"key":"hi","value":" there"}]"#;
-        secret_file.assert(predicate::str::contains(secrets));
+        let secrets = Secret::fetch_all(save_dir.path())?;
+        let secret = secrets.first().unwrap().open(&config.keypair(), u64::MAX)?;
+        assert_eq!(secret.value, " there");
 
         save_dir.close()?;
         Ok(())
@@ -192,7 +447,14 @@ mod tests {
 
         let message_file = save_dir.child("messages.txt");
         message_file.assert(predicate::path::exists());
-        message_file.assert(predicate::str::contains("new message and another one too"));
+        message_file.assert(predicate::str::contains("new message and another one too").not());
+
+        let messages = ItemMessage::fetch_all(save_dir.path())?;
+        let message = messages
+            .first()
+            .unwrap()
+            .open(&config.keypair(), u64::MAX)?;
+        assert_eq!(message.msg(), "new message and another one too");
 
         save_dir.close()?;
         Ok(())
@@ -216,11 +478,100 @@ mod tests {
 
         let saved_file = save_dir.child("sample.txt");
         saved_file.assert(predicate::path::exists());
-        saved_file.assert(predicate::str::contains(
-            "A test\nActual content\nMore content\nAnother test",
-        ));
+
+        let data =
+            ItemFile::read_saved(save_dir.path(), "sample.txt", &config.keypair(), u64::MAX)?;
+        assert_eq!(data, b"A test\nActual content\nMore content\nAnother test");
+
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn signed_item() -> Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+
+        let mut item = Item::new("foo,bar".to_string(), ItemType::Secret)?;
+        assert!(item
+            .verify(&sender.public().to_peer_id(), &recipient)
+            .is_err());
+
+        item.seal(&recipient, true)?;
+        item.sign(&sender, &recipient)?;
+        item.verify(&sender.public().to_peer_id(), &recipient)?;
+
+        let impostor = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(item.verify(&impostor, &recipient).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sealed_state() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let config = make_config(save_dir.path().to_str().unwrap())?;
+        let local_peer_id = config.keypair().public().to_peer_id();
+
+        //what the payload starts with does not make an item sealed
+        let mut item = Item::new("sealed:AAAA".to_string(), ItemType::Message)?;
+        assert!(!item.is_sealed());
+        item.seal(&local_peer_id, false)?;
+        assert!(item.is_sealed());
+        assert!(item.seal(&local_peer_id, false).is_err());
+        assert_eq!(
+            item.open(&config.keypair(), u64::MAX, true)?,
+            b"sealed:AAAA"
+        );
+
+        //sealed items are only saved if they open with our key
+        let mut forged = Item::new("hi there".to_string(), ItemType::Message)?;
+        forged.sealed = true;
+        assert!(forged.save(&config).is_err());
+        let mut other = Item::new("hi there".to_string(), ItemType::Message)?;
+        other.seal(&Keypair::generate_ed25519().public().to_peer_id(), false)?;
+        assert!(other.save(&config).is_err());
+        item.save(&config)?;
 
         save_dir.close()?;
         Ok(())
     }
+
+    #[test]
+    fn signed_expiry() -> Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+
+        let mut item = Item::new("foo,bar".to_string(), ItemType::Secret)?;
+        item.set_expiry(Expiry::new(Some(60), true).as_ref());
+        item.set_expiry(None);
+        item.seal(&recipient, true)?;
+        item.sign(&sender, &recipient)?;
+        assert_eq!(item.name(), "foo");
+        item.verify(&sender.public().to_peer_id(), &recipient)?;
+
+        item.secret.as_mut().unwrap().expiry = None;
+        assert!(item
+            .verify(&sender.public().to_peer_id(), &recipient)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_payload() -> Result<()> {
+        let item = Item::new("foo,bar".to_string(), ItemType::Secret)?;
+        let json = serde_json::to_string(&item)?;
+        assert_eq!(serde_json::from_str::<Item>(&json)?, item);
+
+        let forged = json.replacen("\"Secret\"", "\"File\"", 1);
+        assert!(serde_json::from_str::<Item>(&forged).is_err());
+        let empty = serde_json::json!({
+            "item_type": "Message",
+            "message": null,
+            "secret": null,
+            "file": null,
+            "signature": null,
+        });
+        assert!(serde_json::from_value::<Item>(empty).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,116 @@
+//Secrets can belong to a project and an environment, so `DATABASE_URL` for staging and for prod
+//are kept apart. A namespace is written `project@environment`, and a secret in one `project@environment/KEY`.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+use super::Secret;
+
+/// Selects the secrets of a project and environment. Left out, either matches any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Namespace {
+    pub project: Option<String>,
+    pub environment: Option<String>,
+}
+
+impl Namespace {
+    pub fn new(project: Option<String>, environment: Option<String>) -> Result<Namespace> {
+        let namespace = Namespace {
+            project,
+            environment,
+        };
+        namespace.check()?;
+        Ok(namespace)
+    }
+
+    pub fn of(secret: &Secret) -> Namespace {
+        Namespace {
+            project: secret.project.clone(),
+            environment: secret.environment.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.project.is_none() && self.environment.is_none()
+    }
+
+    /// Names are letters, digits, `_`, `-` and `.`, and start with a letter or digit, as they
+    /// name the files namespaced secrets are saved in.
+    pub fn check(&self) -> Result<()> {
+        for name in self.project.iter().chain(&self.environment) {
+            let valid = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c));
+            if !valid {
+                return Err(anyhow!("Invalid project or environment name {name:?}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `secret` is in the project and environment selected.
+    pub fn contains(&self, secret: &Secret) -> bool {
+        let matches = |selected: &Option<String>, name: &Option<String>| {
+            selected.is_none() || selected == name
+        };
+        matches(&self.project, &secret.project) && matches(&self.environment, &secret.environment)
+    }
+
+    /// Puts `secret` in this namespace, unless it already is in one.
+    pub fn apply(&self, secret: &mut Secret) {
+        if Namespace::of(secret).is_empty() {
+            secret.project = self.project.clone();
+            secret.environment = self.environment.clone();
+        }
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(project) = &self.project {
+            write!(f, "{project}")?;
+        }
+        if let Some(environment) = &self.environment {
+            write!(f, "@{environment}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::Namespace;
+    use crate::item::Secret;
+
+    #[test]
+    fn namespaces() -> Result<()> {
+        let prod = Namespace::new(Some("billing".into()), Some("prod".into()))?;
+        assert_eq!(prod.to_string(), "billing@prod");
+        assert_eq!(
+            Namespace::new(None, Some("prod".into()))?.to_string(),
+            "@prod"
+        );
+        assert!(Namespace::new(Some("../etc".into()), None).is_err());
+        assert!(Namespace::new(None, Some("prod@eu".into())).is_err());
+
+        let mut secret = Secret::secret_from_string("DATABASE_URL=postgres://db".into())?;
+        assert_eq!(secret.name(), "DATABASE_URL");
+        assert!(!prod.contains(&secret));
+
+        prod.apply(&mut secret);
+        assert_eq!(secret.name(), "billing@prod/DATABASE_URL");
+        assert!(prod.contains(&secret));
+        assert!(Namespace::default().contains(&secret));
+        assert!(Namespace::new(Some("billing".into()), None)?.contains(&secret));
+        assert!(!Namespace::new(None, Some("staging".into()))?.contains(&secret));
+
+        let staging = Namespace::new(None, Some("staging".into()))?;
+        staging.apply(&mut secret);
+        assert_eq!(Namespace::of(&secret), prod);
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+//Seals item payloads to the recipient's identity key so that only the recipient can read them.
+//The recipient's Ed25519 key is converted to its X25519 form, a shared key is agreed with a
+//one-off ephemeral key, and the payload is encrypted with ChaCha20-Poly1305.
+
+use std::{fmt, io::Read, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use hkdf::Hkdf;
+use libp2p::{identity, PeerId};
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::format_size;
+
+const MAGIC: &[u8; 4] = b"SCS\x01";
+const SEALED_PREFIX: &str = "sealed:";
+const INFO: &[u8] = b"scs-sealed-v1";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+const RAW_PAYLOAD: u8 = 0;
+const ZSTD_PAYLOAD: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sealed {
+    ephemeral: [u8; KEY_LEN],
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+impl Sealed {
+    /// Encrypts `plaintext` to the key embedded in `recipient`.
+    /// Compression has to happen before encryption, as ciphertext does not compress.
+    pub fn seal(recipient: &PeerId, plaintext: &[u8], compress: bool) -> Result<Sealed> {
+        let recipient_key = x25519_public(recipient)?;
+
+        let mut ephemeral_bytes = [0u8; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut ephemeral_bytes);
+        let ephemeral = StaticSecret::from(ephemeral_bytes);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+
+        let shared = ephemeral.diffie_hellman(&recipient_key);
+        let cipher = cipher(shared.as_bytes(), &ephemeral_public, &recipient_key)?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let payload = frame(plaintext, compress)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), payload.as_slice())
+            .map_err(|_| anyhow!("Failed to encrypt item"))?;
+
+        Ok(Sealed {
+            ephemeral: ephemeral_public.to_bytes(),
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts the payload with the recipient's identity key. Payloads that hold more than
+    /// `limit` bytes once decompressed are refused.
+    pub fn open(&self, keypair: &identity::Keypair, limit: u64) -> Result<Vec<u8>> {
+        self.open_received(keypair, limit, true)
+    }
+
+    /// Like `open`, also refusing payloads that were compressed unless `compression` is on, for
+    /// items received from a peer.
+    pub fn open_received(
+        &self,
+        keypair: &identity::Keypair,
+        limit: u64,
+        compression: bool,
+    ) -> Result<Vec<u8>> {
+        let payload = self.decrypt(keypair)?;
+        if payload.first() == Some(&ZSTD_PAYLOAD) && !compression {
+            return Err(anyhow!(
+                "Item was compressed, but compression is turned off"
+            ));
+        }
+        unframe(&payload, limit)
+    }
+
+    fn decrypt(&self, keypair: &identity::Keypair) -> Result<Vec<u8>> {
+        let secret = x25519_secret(keypair)?;
+        let ephemeral = PublicKey::from(self.ephemeral);
+        let shared = secret.diffie_hellman(&ephemeral);
+        let cipher = cipher(shared.as_bytes(), &ephemeral, &PublicKey::from(&secret))?;
+
+        cipher
+            .decrypt(Nonce::from_slice(&self.nonce), self.ciphertext.as_slice())
+            .map_err(|_| anyhow!("Failed to decrypt item. It was sealed to a different key"))
+    }
+
+    pub fn is_sealed(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    pub fn is_sealed_str(data: &str) -> bool {
+        data.starts_with(SEALED_PREFIX)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(MAGIC.len() + KEY_LEN + NONCE_LEN + self.ciphertext.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.ephemeral);
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Sealed> {
+        if !Sealed::is_sealed(bytes) || bytes.len() < MAGIC.len() + KEY_LEN + NONCE_LEN {
+            return Err(anyhow!("Item is not sealed"));
+        }
+        let bytes = &bytes[MAGIC.len()..];
+        let (ephemeral, bytes) = bytes.split_at(KEY_LEN);
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+
+        Ok(Sealed {
+            ephemeral: ephemeral.try_into()?,
+            nonce: nonce.try_into()?,
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for Sealed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{SEALED_PREFIX}{}", STANDARD.encode(self.to_bytes()))
+    }
+}
+
+impl FromStr for Sealed {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let encoded = s
+            .strip_prefix(SEALED_PREFIX)
+            .ok_or_else(|| anyhow!("Item is not sealed"))?;
+        let bytes = STANDARD
+            .decode(encoded)
+            .context("Sealed item is not valid base64")?;
+        Sealed::from_bytes(&bytes)
+    }
+}
+
+/// Returns the X25519 form of the Ed25519 key embedded in `peer_id`.
+fn x25519_public(peer_id: &PeerId) -> Result<PublicKey> {
+    let key = public_key(peer_id)?;
+    let key = key
+        .try_into_ed25519()
+        .map_err(|_| anyhow!("PeerId {peer_id} is not an Ed25519 key"))?;
+    let point = CompressedEdwardsY(key.to_bytes())
+        .decompress()
+        .ok_or_else(|| anyhow!("PeerId {peer_id} is not a valid Ed25519 key"))?;
+    Ok(PublicKey::from(point.to_montgomery().to_bytes()))
+}
+
+/// Returns the public key inlined in `peer_id`.
+/// Keys of 42 bytes or less, which includes all Ed25519 keys, are inlined with the identity hash.
+pub fn public_key(peer_id: &PeerId) -> Result<identity::PublicKey> {
+    let bytes = peer_id.to_bytes();
+    match bytes.as_slice() {
+        [0, len, key @ ..] if *len as usize == key.len() => {
+            identity::PublicKey::try_decode_protobuf(key)
+                .map_err(|err| anyhow!("Failed to decode the key of {peer_id}: {err}"))
+        }
+        _ => Err(anyhow!("PeerId {peer_id} does not embed its public key")),
+    }
+}
+
+/// Returns the X25519 form of the Ed25519 identity key.
+fn x25519_secret(keypair: &identity::Keypair) -> Result<StaticSecret> {
+    let keypair = keypair
+        .clone()
+        .try_into_ed25519()
+        .map_err(|_| anyhow!("Only Ed25519 identities can open sealed items"))?;
+    let hash = Sha512::digest(keypair.secret().as_ref());
+    let mut bytes = [0u8; KEY_LEN];
+    bytes.copy_from_slice(&hash[..KEY_LEN]);
+    Ok(StaticSecret::from(bytes))
+}
+
+fn cipher(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> Result<ChaCha20Poly1305> {
+    let mut salt = Vec::with_capacity(2 * KEY_LEN);
+    salt.extend_from_slice(ephemeral.as_bytes());
+    salt.extend_from_slice(recipient.as_bytes());
+
+    let mut key = [0u8; KEY_LEN];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(INFO, &mut key)
+        .map_err(|_| anyhow!("Failed to derive item key"))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn frame(plaintext: &[u8], compress: bool) -> Result<Vec<u8>> {
+    if compress {
+        let compressed = zstd::encode_all(plaintext, 3)?;
+        if compressed.len() < plaintext.len() - plaintext.len() / 10 {
+            let mut payload = vec![ZSTD_PAYLOAD];
+            payload.extend(compressed);
+            return Ok(payload);
+        }
+    }
+    let mut payload = vec![RAW_PAYLOAD];
+    payload.extend_from_slice(plaintext);
+    Ok(payload)
+}
+
+/// Reverses [`frame`], refusing to decompress past `limit` bytes.
+fn unframe(payload: &[u8], limit: u64) -> Result<Vec<u8>> {
+    match payload.split_first() {
+        Some((&RAW_PAYLOAD, plaintext)) => Ok(plaintext.to_vec()),
+        Some((&ZSTD_PAYLOAD, compressed)) => {
+            let mut plaintext = vec![];
+            zstd::stream::Decoder::new(compressed)?
+                .take(limit.saturating_add(1))
+                .read_to_end(&mut plaintext)?;
+            if plaintext.len() as u64 > limit {
+                return Err(anyhow!(
+                    "Sealed item holds more than {} once decompressed",
+                    format_size(limit)
+                ));
+            }
+            Ok(plaintext)
+        }
+        _ => Err(anyhow!("Sealed item has an unknown format")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::{public_key, Sealed};
+
+    #[test]
+    fn seal_and_open() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+
+        let sealed = Sealed::seal(&peer_id, b"hunter2", false)?;
+        assert!(!sealed.to_bytes().windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(sealed.open(&keypair, 7)?, b"hunter2");
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_key() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let sealed = Sealed::seal(&keypair.public().to_peer_id(), b"hunter2", false)?;
+
+        let other = Keypair::generate_ed25519();
+        let err = sealed.open(&other, u64::MAX).unwrap_err();
+        assert!(err.to_string().contains("different key"));
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_payload() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let text = "SELECT * FROM users;\n".repeat(1000);
+
+        let sealed = Sealed::seal(&keypair.public().to_peer_id(), text.as_bytes(), true)?;
+        assert!(sealed.to_bytes().len() < text.len() / 10);
+        assert_eq!(sealed.open(&keypair, u64::MAX)?, text.as_bytes());
+        assert!(sealed.open_received(&keypair, u64::MAX, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decompression_limit() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let data = vec![0u8; 1024 * 1024];
+
+        let sealed = Sealed::seal(&keypair.public().to_peer_id(), &data, true)?;
+        assert!(sealed.to_bytes().len() < 1024);
+        let err = sealed.open(&keypair, 1024).unwrap_err();
+        assert!(err.to_string().contains("more than"));
+        assert_eq!(sealed.open(&keypair, data.len() as u64)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn string_form() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let sealed = Sealed::seal(&keypair.public().to_peer_id(), b"bar", false)?;
+
+        let encoded = sealed.to_string();
+        assert!(Sealed::is_sealed_str(&encoded));
+        assert_eq!(Sealed::from_str(&encoded)?, sealed);
+        assert!(Sealed::from_str("bar").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn key_from_peer_id() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        assert_eq!(public_key(&peer_id)?, keypair.public());
+        Ok(())
+    }
+}
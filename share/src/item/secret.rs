@@ -1,63 +1,422 @@
 use std::{
-    fs::OpenOptions,
-    io::{BufReader, BufWriter, Write},
-    path::Path,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
     process::exit,
+    str::FromStr,
 };
 
 use anyhow::{anyhow, Context, Result};
-use serde::{Deserialize, Serialize};
+use dialoguer::{theme::ColorfulTheme, Password};
+use libp2p::{identity::Keypair, PeerId};
+use time::OffsetDateTime;
 use tracing::error;
 
-use super::Secret;
+use super::{digest, sealed::Sealed, version, ItemType, Namespace, Secret};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct SecretResponse {
-    pub status: Status,
-}
+impl Secret {
+    pub fn new(key: String, value: String) -> Secret {
+        Secret {
+            key,
+            value,
+            project: None,
+            environment: None,
+            expiry: None,
+            version: None,
+            history: vec![],
+        }
+    }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub enum Status {
-    Failed,
-    Succes,
-}
+    /// The key, after the namespace when the secret is in one, e.g. `billing@prod/DATABASE_URL`.
+    pub fn name(&self) -> String {
+        let namespace = Namespace::of(self);
+        match namespace.is_empty() {
+            true => self.key.clone(),
+            false => format!("{namespace}/{}", self.key),
+        }
+    }
 
-impl Secret {
-    pub fn secret_from_string(secret: String) -> Result<Secret> {
-        let key_value_pair = secret.split(',').collect::<Vec<_>>();
-        if key_value_pair.len() > 2 {
-            return Err(anyhow!("Key Value Pair for secret is incompatible"));
+    /// The name the sender signs, which holds when the secret expires so it can't be dropped on
+    /// the way. Keys can't hold a newline.
+    pub fn signed_name(&self) -> String {
+        match &self.expiry {
+            Some(expiry) => format!("{}\n{}", self.name(), expiry.label()),
+            None => self.name(),
         }
-        if key_value_pair.len() < 2 {
+    }
+
+    pub fn digest(&self) -> Vec<u8> {
+        digest(
+            &ItemType::Secret,
+            &self.signed_name(),
+            self.value.as_bytes(),
+        )
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry
+            .as_ref()
+            .is_some_and(|expiry| expiry.is_expired(now))
+    }
+
+    pub fn burns(&self) -> bool {
+        self.expiry.as_ref().is_some_and(|expiry| expiry.burn)
+    }
+
+    /// Parses `KEY=VALUE` or `KEY,VALUE`, splitting on the first separator only, so values can
+    /// hold commas and `=`. Values in double quotes take the escapes `\n`, `\r`, `\t`, `\"` and
+    /// `\\`; values in single quotes are taken as they are. Values can span several lines.
+    pub fn secret_from_string(secret: String) -> Result<Secret> {
+        let (key, value) = match secret.find(['=', ',']) {
+            Some(split) => (&secret[..split], &secret[split + 1..]),
+            None => return Err(anyhow!("Key or Value not found for {secret}")),
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains('\n') {
             return Err(anyhow!("Key or Value not found for {secret}"));
         }
-        let secret = Secret {
-            key: key_value_pair.first().unwrap().to_string(),
-            value: key_value_pair.get(1).unwrap().to_string(),
+
+        let value = match value.trim_start().chars().next() {
+            Some(quote @ ('"' | '\'')) => unquote(value.trim(), quote)
+                .with_context(|| format!("Invalid value for the secret {key}"))?,
+            _ => value.to_string(),
         };
-        Ok(secret)
+        Ok(Secret::new(key.to_string(), value))
     }
 
-    pub fn save_secret(&self, path: &Path) -> Result<()> {
-        let secret_default_path = path.join("secrets.json");
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(secret_default_path)?;
+    /// Parses the secrets passed with `-s` by `secret_from_string`, and those passed with
+    /// `--secret-file KEY=path`, `--secret-env KEY=NAME` and `--secret-stdin KEY`, whose values are
+    /// read from the file, environment variable or stdin named so they need not be on the command
+    /// line. Passing only `KEY` to `-s` asks for its value without showing what is typed.
+    pub fn from_args(
+        args: &[String],
+        files: &[String],
+        envs: &[String],
+        stdin: Option<&str>,
+    ) -> Result<Vec<Secret>> {
+        let mut secrets = args
+            .iter()
+            .map(|arg| match is_key(arg.trim()) {
+                true => read_source(arg.trim(), Source::Prompt)
+                    .map(|value| Secret::new(arg.trim().to_string(), value)),
+                false => Secret::secret_from_string(arg.clone()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for file in files {
+            let (key, path) = reference(file)?;
+            secrets.push(Secret::new(
+                key.to_string(),
+                read_source(key, Source::File(path))?,
+            ));
+        }
+        for env in envs {
+            let (key, name) = reference(env)?;
+            secrets.push(Secret::new(
+                key.to_string(),
+                read_source(key, Source::Env(name))?,
+            ));
+        }
+        if let Some(key) = stdin {
+            let key = key.trim();
+            if !is_key(key) {
+                return Err(anyhow!("Invalid key {key} to read from stdin"));
+            }
+            secrets.push(Secret::new(
+                key.to_string(),
+                read_source(key, Source::Stdin)?,
+            ));
+        }
+        Ok(secrets)
+    }
+
+    /// Secrets are saved in `secrets.json`, and namespaced ones apart from them in
+    /// `secrets/<namespace>.json`, e.g. `secrets/billing@prod.json`. A key saved again gets the new
+    /// value, and keeps at most `history` earlier ones. The time a temporary secret can be read for
+    /// starts when it is saved.
+    pub fn save_secret(&self, path: &Path, history: usize) -> Result<()> {
+        let file = secrets_file(path, &Namespace::of(self))?;
+        let mut saved = read_secrets(&file)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut secret = self.clone();
+        if let Some(expiry) = secret.expiry.as_mut() {
+            expiry.start(now);
+        }
+        version::receive(&mut saved, secret, now, history);
+        write_secrets(&file, &saved)
+    }
+
+    /// The current value of every saved secret that has not expired, those without a namespace
+    /// first.
+    pub fn fetch_all(path: &Path) -> Result<Vec<Secret>> {
+        Ok(Secret::fetch_versions(path)?
+            .iter()
+            .map(Secret::current)
+            .collect())
+    }
+
+    /// Like `fetch_all`, with the version of each secret and its earlier values.
+    pub fn fetch_versions(path: &Path) -> Result<Vec<Secret>> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut secrets = vec![];
+        for file in secret_files(path) {
+            let saved = read_secrets(&file)?;
+            secrets.extend(saved.into_iter().filter(|secret| !secret.is_expired(now)));
+        }
+        Ok(secrets)
+    }
+
+    /// Makes version `to` of this saved secret, or else the value before its current one, its
+    /// current value again, as a new version. Returns the secret restored.
+    pub fn rollback(&self, path: &Path, to: Option<u32>, history: usize) -> Result<Secret> {
+        let file = secrets_file(path, &Namespace::of(self))?;
+        let mut saved = read_secrets(&file)?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let restored = version::restore(&mut saved, &self.name(), to, now, history)?;
+        write_secrets(&file, &saved)?;
+        Ok(restored)
+    }
+
+    /// Deletes the saved secrets that have expired. Returns how many there were.
+    pub fn purge_expired(path: &Path) -> Result<usize> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        remove_saved(path, |secret| secret.is_expired(now))
+    }
 
-        let reader = BufReader::new(file.try_clone()?);
-        //in case the file was just created and it is empty, automatically add a column and then push the result
-        let empty: Result<Vec<Secret>> = Ok(vec![]);
-        let mut contents: Vec<Secret> = serde_json::from_reader(reader).or(empty)?;
-        contents.push(self.clone());
+    /// Deletes the saved secrets in `read` that burn after reading, with their earlier values.
+    pub fn burn(path: &Path, read: &[Secret]) -> Result<usize> {
+        remove_saved(path, |secret| secret.burns() && read.contains(secret))
+    }
 
-        let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, &contents)?;
-        writer.flush().context("Failed to save secrets")?;
+    /// The saved secrets in `namespace`.
+    pub fn fetch(path: &Path, namespace: &Namespace) -> Result<Vec<Secret>> {
+        let secrets = Secret::fetch_all(path)?;
+        Ok(secrets
+            .into_iter()
+            .filter(|secret| namespace.contains(secret))
+            .collect())
+    }
 
+    pub fn seal(&mut self, recipient: &PeerId, compress: bool) -> Result<()> {
+        self.value = Sealed::seal(recipient, self.value.as_bytes(), compress)?.to_string();
         Ok(())
     }
+
+    /// Decrypts a saved secret. Secrets are saved sealed, so only the ones saved before items
+    /// were sealed lack the `sealed:` prefix.
+    pub fn open(&self, keypair: &Keypair, limit: u64) -> Result<Secret> {
+        if !Sealed::is_sealed_str(&self.value) {
+            return Ok(self.clone());
+        }
+        let value = Sealed::from_str(&self.value)?.open(keypair, limit)?;
+        let value = String::from_utf8(value).context("Secret value is not valid UTF-8")?;
+        Ok(Secret {
+            value,
+            ..self.clone()
+        })
+    }
+}
+
+/// The value between the quotes that `value` starts and ends with, with its escapes replaced
+/// when they are double quotes.
+fn unquote(value: &str, quote: char) -> Result<String> {
+    match take_quoted(value, quote)? {
+        None => Err(anyhow!("The closing {quote} is missing")),
+        Some((_, rest)) if !rest.is_empty() => {
+            Err(anyhow!("Nothing may follow the closing {quote}"))
+        }
+        Some((unquoted, _)) => Ok(unquoted),
+    }
+}
+
+/// Reads the value quoted by `quote` at the start of `value`, giving it and what follows the
+/// closing quote. None if the closing quote is missing.
+pub(super) fn take_quoted(value: &str, quote: char) -> Result<Option<(String, &str)>> {
+    let mut chars = value.char_indices().skip(1);
+    let mut unquoted = String::new();
+    loop {
+        match chars.next() {
+            None => return Ok(None),
+            Some((at, c)) if c == quote => return Ok(Some((unquoted, &value[at + 1..]))),
+            Some((_, '\\')) if quote == '"' => match chars.next() {
+                Some((_, 'n')) => unquoted.push('\n'),
+                Some((_, 'r')) => unquoted.push('\r'),
+                Some((_, 't')) => unquoted.push('\t'),
+                Some((_, c @ ('"' | '\\'))) => unquoted.push(c),
+                Some((_, c)) => return Err(anyhow!("Unknown escape \\{c}")),
+                None => return Ok(None),
+            },
+            Some((_, c)) => unquoted.push(c),
+        }
+    }
+}
+
+fn secret_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.join("secrets.json")];
+    if let Ok(entries) = fs::read_dir(path.join("secrets")) {
+        let mut namespaced = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect::<Vec<_>>();
+        namespaced.sort();
+        files.extend(namespaced);
+    }
+    files.retain(|file| file.exists());
+    files
+}
+
+fn secrets_file(path: &Path, namespace: &Namespace) -> Result<PathBuf> {
+    namespace.check()?;
+    if namespace.is_empty() {
+        return Ok(path.join("secrets.json"));
+    }
+    let dir = path.join("secrets");
+    fs::create_dir_all(&dir).context("Failed to create the secrets directory")?;
+    Ok(dir.join(format!("{namespace}.json")))
+}
+
+/// The secrets saved in `file`, none if it is missing or empty.
+fn read_secrets(file: &Path) -> Result<Vec<Secret>> {
+    let contents = match fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err).context("Failed to read saved secrets"),
+    };
+    if contents.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let saved = serde_json::from_str(&contents).context("Failed to read saved secrets")?;
+    Ok(version::fold(saved))
+}
+
+fn write_secrets(file: &Path, secrets: &[Secret]) -> Result<()> {
+    write_secrets_with(file, secrets, write_all)
+}
+
+/// Writes the contents of a file of secrets. Only swapped in tests, to fail part way.
+type Writer = fn(&mut File, &[u8]) -> io::Result<()>;
+
+fn write_all(file: &mut File, contents: &[u8]) -> io::Result<()> {
+    file.write_all(contents)
+}
+
+/// Writes `secrets` to a new file beside `file` and renames it over `file` once it is on disk, so
+/// a failure part way leaves the secrets saved before as they were.
+fn write_secrets_with(file: &Path, secrets: &[Secret], write: Writer) -> Result<()> {
+    let contents = serde_json::to_vec(secrets)?;
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = file.with_file_name(format!(".{name}.{:016x}.tmp", rand::random::<u64>()));
+    let mut temp = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .context("Failed to save secrets")?;
+    let written = write(&mut temp, &contents)
+        .and_then(|_| temp.sync_all())
+        .and_then(|_| fs::rename(&temp_path, file));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err).context("Failed to save secrets");
+    }
+    #[cfg(unix)]
+    if let Some(dir) = file.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Removes the saved secrets `removed` picks. The secrets kept are saved in a new file first, and
+/// the old one is overwritten through a handle still open on it, so the values removed are not
+/// left behind on disk and a failure part way loses none of the others.
+fn remove_saved(path: &Path, removed: impl Fn(&Secret) -> bool) -> Result<usize> {
+    remove_saved_with(path, removed, write_all)
+}
+
+fn remove_saved_with(
+    path: &Path,
+    removed: impl Fn(&Secret) -> bool,
+    write: Writer,
+) -> Result<usize> {
+    let mut count = 0;
+    for file in secret_files(path) {
+        let (gone, kept): (Vec<_>, Vec<_>) = read_secrets(&file)?
+            .into_iter()
+            .partition(|secret| removed(&secret.current()));
+        if gone.is_empty() {
+            continue;
+        }
+        count += gone.len();
+
+        let mut old = OpenOptions::new().write(true).open(&file)?;
+        match kept.is_empty() {
+            true => fs::remove_file(&file)?,
+            false => write_secrets_with(&file, &kept, write)?,
+        }
+        let len = old.metadata()?.len();
+        old.write_all(&vec![0; len as usize])?;
+        old.sync_all()?;
+    }
+    Ok(count)
+}
+
+/// Where the value of a secret is read from, when it is not on the command line.
+enum Source<'a> {
+    File(&'a str),
+    Env(&'a str),
+    Stdin,
+    Prompt,
+}
+
+/// Splits `KEY=reference` or `KEY,reference` into the key and where its value is read from.
+fn reference(arg: &str) -> Result<(&str, &str)> {
+    match arg.find(['=', ',']) {
+        Some(split) if is_key(arg[..split].trim()) && split + 1 < arg.len() => {
+            Ok((arg[..split].trim(), &arg[split + 1..]))
+        }
+        _ => Err(anyhow!("Key or source not found for {arg}")),
+    }
+}
+
+/// Keys that can be given alone to be asked for their value, or named to be read from a source.
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+}
+
+fn read_source(key: &str, source: Source) -> Result<String> {
+    match source {
+        Source::File(path) => fs::read_to_string(path)
+            .map(strip_newline)
+            .with_context(|| format!("Failed to read the secret {key} from {path}")),
+        Source::Env(name) => env::var(name)
+            .with_context(|| format!("No environment variable {name} for the secret {key}")),
+        Source::Stdin if !io::stdin().is_terminal() => {
+            let mut value = String::new();
+            io::stdin()
+                .read_to_string(&mut value)
+                .with_context(|| format!("Failed to read the secret {key} from stdin"))?;
+            Ok(strip_newline(value))
+        }
+        Source::Stdin | Source::Prompt => Ok(Password::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Value of {key}"))
+            .interact()?),
+    }
+}
+
+/// Drops the newline files and piped commands usually end with.
+fn strip_newline(mut value: String) -> String {
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    value
 }
 
 impl From<String> for Secret {
@@ -86,9 +445,11 @@ impl From<&String> for Secret {
 
 #[cfg(test)]
 mod tests {
-    use super::Secret;
+    use super::{remove_saved_with, write_secrets_with, Namespace, Secret};
+    use crate::item::Expiry;
     use anyhow::Result;
-    use assert_fs::prelude::{PathAssert, PathChild};
+    use assert_fs::prelude::{FileWriteStr, PathAssert, PathChild};
+    use libp2p::identity::Keypair;
     use predicates::prelude::*;
 
     #[test]
@@ -100,6 +461,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn value_sources() -> Result<()> {
+        let file = assert_fs::NamedTempFile::new("scs_password")?;
+        file.write_str("hunter2\n")?;
+        let name = format!("SCS_TEST_TOKEN_{}", std::process::id());
+        std::env::set_var(&name, "abc");
+        let secrets = Secret::from_args(
+            &["HOME=@home".to_string(), "PATH,env:PATH".to_string()],
+            &[format!("DB_PASSWORD={}", file.path().display())],
+            &[format!("TOKEN,{name}")],
+            None,
+        );
+        std::env::remove_var(&name);
+        let secrets = secrets?;
+        assert_eq!(secrets[0].value, "@home");
+        assert_eq!(secrets[1].value, "env:PATH");
+        assert_eq!(secrets[2].key, "DB_PASSWORD");
+        assert_eq!(secrets[2].value, "hunter2");
+        assert_eq!(secrets[3].key, "TOKEN");
+        assert_eq!(secrets[3].value, "abc");
+
+        let unset = format!("SCS_TEST_UNSET_{}", std::process::id());
+        assert!(Secret::from_args(&[], &[], &[format!("TOKEN={unset}")], None).is_err());
+        assert!(Secret::from_args(&[], &["A=/no/such/file".to_string()], &[], None).is_err());
+        assert!(Secret::from_args(&[], &["=path".to_string()], &[], None).is_err());
+        assert!(Secret::from_args(&[], &[], &["TOKEN".to_string()], None).is_err());
+        assert!(Secret::from_args(&[], &[], &[], Some("A=B")).is_err());
+        Ok(())
+    }
+
     #[test]
     fn secret() -> Result<()> {
         let secret = Secret::from("hi,there".to_string());
@@ -108,19 +499,244 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn separators() -> Result<()> {
+        let secret =
+            Secret::secret_from_string("DATABASE_URL=postgres://u:p@host/db?a=b,c".into())?;
+        assert_eq!(secret.key, "DATABASE_URL");
+        assert_eq!(secret.value, "postgres://u:p@host/db?a=b,c");
+
+        let secret = Secret::secret_from_string("HOSTS,a,b,c".into())?;
+        assert_eq!(secret.value, "a,b,c");
+
+        let secret = Secret::secret_from_string("hi, there".into())?;
+        assert_eq!(secret.value, " there");
+
+        let secret = Secret::secret_from_string("EMPTY=".into())?;
+        assert_eq!(secret.value, "");
+
+        assert!(Secret::secret_from_string("=value".into()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn quoted_values() -> Result<()> {
+        let secret = Secret::secret_from_string(r#"CONFIG={"a": 1, "b": [2, 3]}"#.into())?;
+        assert_eq!(secret.value, r#"{"a": 1, "b": [2, 3]}"#);
+
+        let secret = Secret::secret_from_string(r#"GREETING="hi, \"there\"\n\tbye\\""#.into())?;
+        assert_eq!(secret.value, "hi, \"there\"\n\tbye\\");
+
+        let secret = Secret::secret_from_string(r#"PATTERN='\d+\n'"#.into())?;
+        assert_eq!(secret.value, r#"\d+\n"#);
+
+        let secret = Secret::secret_from_string(r#"SPACED= "a b" "#.into())?;
+        assert_eq!(secret.value, "a b");
+
+        for invalid in [r#"A="open"#, r#"A="a"b"#, r#"A="\q""#, "A='open"] {
+            let err = Secret::secret_from_string(invalid.into()).unwrap_err();
+            assert!(err.to_string().contains("Invalid value for the secret A"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn multi_line_values() -> Result<()> {
+        let pem = "-----BEGIN CERTIFICATE-----\nMIIB=,\n-----END CERTIFICATE-----";
+        let secret = Secret::secret_from_string(format!("CERT={pem}"))?;
+        assert_eq!(secret.value, pem);
+
+        let secret = Secret::secret_from_string(
+            "CERT=\"-----BEGIN CERTIFICATE-----\\nMIIB\\n-----END CERTIFICATE-----\"".into(),
+        )?;
+        assert_eq!(secret.value.lines().count(), 3);
+        Ok(())
+    }
+
     #[test]
     fn save_secret() -> Result<()> {
         let save_dir = assert_fs::TempDir::new()?;
         let secret = Secret::from("foo,bar".to_string());
-        secret.save_secret(&save_dir.path())?;
+        secret.save_secret(save_dir.path(), 10)?;
 
         let secret_file = save_dir.child("secrets.json");
         secret_file.assert(predicate::path::exists());
 
-        let secrets = r#"[{"key":"foo","value":"bar"}]"#;
+        let secrets = r#"[{"key":"foo","value":"bar","version":{"number":1,"received_at":"#;
         secret_file.assert(predicate::str::contains(secrets));
 
         save_dir.close()?;
         Ok(())
     }
+
+    #[test]
+    fn save_secrets() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        Secret::from("foo,bar".to_string()).save_secret(save_dir.path(), 10)?;
+        Secret::from("hi,there".to_string()).save_secret(save_dir.path(), 10)?;
+
+        //the file holds one list, which every secret saved is added to
+        let saved = std::fs::read_to_string(save_dir.child("secrets.json").path())?;
+        let secrets: Vec<Secret> = serde_json::from_str(&saved)?;
+        let keys = secrets
+            .iter()
+            .map(|secret| secret.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["foo", "hi"]);
+
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn namespaced_secrets() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let prod = Namespace::new(Some("billing".into()), Some("prod".into()))?;
+        let staging = Namespace::new(Some("billing".into()), Some("staging".into()))?;
+        for (namespace, value) in [(&prod, "prod"), (&staging, "staging")] {
+            let mut secret = Secret::new("DATABASE_URL".into(), value.into());
+            namespace.apply(&mut secret);
+            secret.save_secret(save_dir.path(), 10)?;
+        }
+        Secret::new("DATABASE_URL".into(), "local".into()).save_secret(save_dir.path(), 10)?;
+
+        save_dir
+            .child("secrets/billing@prod.json")
+            .assert(predicate::str::contains(r#""environment":"prod""#));
+        let values = |secrets: Vec<Secret>| {
+            secrets
+                .into_iter()
+                .map(|secret| secret.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values(Secret::fetch_all(save_dir.path())?),
+            vec!["local", "prod", "staging"]
+        );
+        assert_eq!(
+            values(Secret::fetch(save_dir.path(), &staging)?),
+            vec!["staging"]
+        );
+
+        let mut secret = Secret::new("KEY".into(), "value".into());
+        secret.project = Some("../../etc".into());
+        assert!(secret.save_secret(save_dir.path(), 10).is_err());
+
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn versioned_secrets() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        for value in ["a", "b", "c"] {
+            Secret::new("API_KEY".into(), value.into()).save_secret(save_dir.path(), 1)?;
+        }
+
+        let secrets = Secret::fetch_all(save_dir.path())?;
+        assert_eq!(secrets, vec![Secret::new("API_KEY".into(), "c".into())]);
+        let versions = Secret::fetch_versions(save_dir.path())?;
+        assert_eq!(versions[0].number(), 3);
+        assert_eq!(versions[0].history.len(), 1);
+
+        let restored = versions[0].rollback(save_dir.path(), None, 1)?;
+        assert_eq!(restored.value, "b");
+        assert_eq!(Secret::fetch_all(save_dir.path())?[0].value, "b");
+        assert!(versions[0].rollback(save_dir.path(), Some(1), 1).is_err());
+
+        //saved before versions were kept
+        let file = save_dir.child("secrets.json");
+        file.write_str(r#"[{"key":"PORT","value":"80"},{"key":"PORT","value":"8080"}]"#)?;
+        let versions = Secret::fetch_versions(save_dir.path())?;
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].value, "8080");
+        assert_eq!(versions[0].number(), 2);
+        Secret::new("PORT".into(), "443".into()).save_secret(save_dir.path(), 10)?;
+        assert_eq!(Secret::fetch_versions(save_dir.path())?[0].history.len(), 2);
+
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn temporary_secrets() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let mut expired = Secret::new("OLD_TOKEN".into(), "a".into());
+        expired.expiry = Expiry::new(Some(60), false);
+        expired.save_secret(save_dir.path(), 10)?;
+        let mut burned = Secret::new("ONE_TIME".into(), "b".into());
+        burned.expiry = Expiry::new(None, true);
+        burned.save_secret(save_dir.path(), 10)?;
+        Secret::new("KEPT".into(), "c".into()).save_secret(save_dir.path(), 10)?;
+
+        //the time starts when the secret is saved
+        let secrets = Secret::fetch_all(save_dir.path())?;
+        assert_eq!(secrets.len(), 3);
+        assert!(secrets[0].expiry.as_ref().unwrap().at.is_some());
+        assert_eq!(Secret::purge_expired(save_dir.path())?, 0);
+
+        let file = save_dir.child("secrets.json");
+        let mut saved: Vec<Secret> = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+        saved[0].expiry.as_mut().unwrap().at = Some(0);
+        file.write_str(&serde_json::to_string(&saved)?)?;
+        assert_eq!(Secret::fetch_all(save_dir.path())?.len(), 2);
+        assert_eq!(Secret::purge_expired(save_dir.path())?, 1);
+        file.assert(predicate::str::contains("OLD_TOKEN").not());
+
+        let secrets = Secret::fetch_all(save_dir.path())?;
+        assert_eq!(Secret::burn(save_dir.path(), &secrets)?, 1);
+        let secrets = Secret::fetch_all(save_dir.path())?;
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(secrets[0].key, "KEPT");
+
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn failed_writes() -> Result<()> {
+        let save_dir = assert_fs::TempDir::new()?;
+        let mut burned = Secret::new("ONE_TIME".into(), "b".into());
+        burned.expiry = Expiry::new(None, true);
+        burned.save_secret(save_dir.path(), 10)?;
+        Secret::new("KEPT".into(), "c".into()).save_secret(save_dir.path(), 10)?;
+        Secret::new("ALSO_KEPT".into(), "d".into()).save_secret(save_dir.path(), 10)?;
+
+        //fails after writing half of what it is given
+        let half = |file: &mut std::fs::File, contents: &[u8]| {
+            std::io::Write::write_all(file, &contents[..contents.len() / 2])?;
+            Err(std::io::Error::other("disk full"))
+        };
+        let file = save_dir.child("secrets.json");
+        assert!(remove_saved_with(save_dir.path(), |secret| secret.burns(), half).is_err());
+        assert!(write_secrets_with(file.path(), &[], half).is_err());
+        assert_eq!(Secret::fetch_all(save_dir.path())?.len(), 3);
+        assert_eq!(std::fs::read_dir(save_dir.path())?.count(), 1);
+
+        let secrets = Secret::fetch_all(save_dir.path())?;
+        assert_eq!(Secret::burn(save_dir.path(), &secrets)?, 1);
+        assert_eq!(Secret::fetch_all(save_dir.path())?.len(), 2);
+        assert_eq!(std::fs::read_dir(save_dir.path())?.count(), 1);
+
+        save_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn seal_secret() -> Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let mut secret = Secret::from("foo,bar".to_string());
+        secret.seal(&keypair.public().to_peer_id(), true)?;
+        assert_eq!(secret.key, "foo");
+        assert_ne!(secret.value, "bar");
+
+        let save_dir = assert_fs::TempDir::new()?;
+        secret.save_secret(save_dir.path(), 10)?;
+        let saved = Secret::fetch_all(save_dir.path())?;
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].open(&keypair, u64::MAX)?.value, "bar");
+
+        save_dir.close()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,117 @@
+//Senders sign every item with their identity key so receivers can prove where it came from.
+//The signature covers the sealed payload as it is stored, so it can be checked again later
+//without decrypting anything.
+
+use anyhow::{anyhow, Result};
+use libp2p::{identity::Keypair, PeerId};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use super::{sealed::public_key, ItemType};
+
+const DOMAIN: &[u8] = b"scs-item-v1";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemSignature {
+    /// Unix timestamp of when the item was signed
+    pub signed_at: i64,
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl ItemSignature {
+    pub fn sign(keypair: &Keypair, recipient: &PeerId, digest: &[u8]) -> Result<ItemSignature> {
+        let signed_at = OffsetDateTime::now_utc().unix_timestamp();
+        let signature = keypair.sign(&signed_bytes(recipient, digest, signed_at))?;
+        Ok(ItemSignature {
+            signed_at,
+            signature,
+        })
+    }
+
+    /// Checks that `sender` signed the item with `digest` for `recipient`.
+    pub fn verify(&self, sender: &PeerId, recipient: &PeerId, digest: &[u8]) -> Result<()> {
+        let key = public_key(sender)?;
+        if key.verify(
+            &signed_bytes(recipient, digest, self.signed_at),
+            &self.signature,
+        ) {
+            Ok(())
+        } else {
+            Err(anyhow!("Signature does not match the sender {sender}"))
+        }
+    }
+}
+
+/// Hashes an item as it is stored: its type, its name and its sealed payload.
+pub fn digest(item_type: &ItemType, name: &str, payload: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{item_type:?}").as_bytes());
+    hasher.update((name.len() as u64).to_be_bytes());
+    hasher.update(name.as_bytes());
+    hasher.update(payload);
+    hasher.finalize().to_vec()
+}
+
+fn signed_bytes(recipient: &PeerId, digest: &[u8], signed_at: i64) -> Vec<u8> {
+    let mut bytes = DOMAIN.to_vec();
+    bytes.extend(recipient.to_bytes());
+    bytes.extend_from_slice(digest);
+    bytes.extend_from_slice(&signed_at.to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::{digest, ItemSignature};
+    use crate::item::ItemType;
+
+    #[test]
+    fn sign_and_verify() -> Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let digest = digest(&ItemType::Secret, "foo", b"sealed:bar");
+
+        let signature = ItemSignature::sign(&sender, &recipient, &digest)?;
+        signature.verify(&sender.public().to_peer_id(), &recipient, &digest)?;
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_sender() -> Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let digest = digest(&ItemType::Secret, "foo", b"sealed:bar");
+
+        let signature = ItemSignature::sign(&sender, &recipient, &digest)?;
+        let impostor = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(signature.verify(&impostor, &recipient, &digest).is_err());
+
+        let other_recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let sender = sender.public().to_peer_id();
+        assert!(signature
+            .verify(&sender, &other_recipient, &digest)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tampered_item() -> Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let signature = ItemSignature::sign(
+            &sender,
+            &recipient,
+            &digest(&ItemType::Secret, "foo", b"sealed:bar"),
+        )?;
+
+        let tampered = digest(&ItemType::Secret, "foo", b"sealed:baz");
+        let sender = sender.public().to_peer_id();
+        assert!(signature.verify(&sender, &recipient, &tampered).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+//Requests other than deliveries are stamped like them, with a random id and the time they were
+//sent, signed along with what they ask for, so that a receiver can refuse one that was captured
+//and sent again.
+
+use anyhow::{anyhow, Result};
+use libp2p::{identity::Keypair, PeerId};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::sealed::public_key;
+
+const DOMAIN: &[u8] = b"scs-request-v1";
+const ID_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stamped<T> {
+    pub content: T,
+    #[serde(with = "serde_bytes")]
+    pub id: Vec<u8>,
+    /// Unix timestamp of when the request was sent
+    pub sent_at: i64,
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl<T: Serialize> Stamped<T> {
+    pub fn new(content: T, keypair: &Keypair, recipient: &PeerId) -> Result<Stamped<T>> {
+        let mut id = vec![0u8; ID_LEN];
+        rand::thread_rng().fill_bytes(&mut id);
+        let sent_at = OffsetDateTime::now_utc().unix_timestamp();
+        let signature = keypair.sign(&signed_bytes(recipient, &id, sent_at, &content)?)?;
+        Ok(Stamped {
+            content,
+            id,
+            sent_at,
+            signature,
+        })
+    }
+
+    /// Checks that `sender` sent this request, with this id and time, to `recipient`.
+    pub fn verify(&self, sender: &PeerId, recipient: &PeerId) -> Result<()> {
+        let key = public_key(sender)?;
+        let bytes = signed_bytes(recipient, &self.id, self.sent_at, &self.content)?;
+        if self.id.len() == ID_LEN && key.verify(&bytes, &self.signature) {
+            Ok(())
+        } else {
+            Err(anyhow!("Request was not signed by the sender {sender}"))
+        }
+    }
+}
+
+fn signed_bytes<T: Serialize>(
+    recipient: &PeerId,
+    id: &[u8],
+    sent_at: i64,
+    content: &T,
+) -> Result<Vec<u8>> {
+    let mut bytes = DOMAIN.to_vec();
+    bytes.extend(recipient.to_bytes());
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&sent_at.to_be_bytes());
+    bytes.extend(serde_cbor::to_vec(content)?);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::identity::Keypair;
+
+    use super::Stamped;
+
+    #[test]
+    fn sign_and_verify() -> Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let keys = vec!["STRIPE_KEY".to_string()];
+
+        let pull = Stamped::new(keys, &sender, &recipient)?;
+        let sender = sender.public().to_peer_id();
+        pull.verify(&sender, &recipient)?;
+        assert!(pull.verify(&recipient, &sender).is_err());
+
+        let mut changed = pull.clone();
+        changed.content.push("DATABASE_URL".to_string());
+        assert!(changed.verify(&sender, &recipient).is_err());
+
+        let mut backdated = pull;
+        backdated.sent_at -= 3600;
+        assert!(backdated.verify(&sender, &recipient).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+//The lines of a chat, kept sealed to our own key like received items, one file per peer.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use libp2p::{identity::Keypair, PeerId};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::sealed::Sealed;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    path: PathBuf,
+}
+
+impl Transcript {
+    pub fn new(save_path: &Path, peer: &PeerId) -> Transcript {
+        Transcript {
+            path: save_path.join("transcripts").join(format!("{peer}.txt")),
+        }
+    }
+
+    /// Appends what `speaker` said, sealed to `owner`.
+    pub fn append(&self, speaker: &str, text: &str, owner: &PeerId) -> Result<()> {
+        fs::create_dir_all(self.path.parent().unwrap())?;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+
+        let now = OffsetDateTime::now_utc().format(&Rfc3339)?;
+        let line = format!("[{now}] {speaker}: {text}");
+        let sealed = Sealed::seal(owner, line.as_bytes(), false)?;
+        writeln!(file, "{sealed}")?;
+        Ok(())
+    }
+
+    pub fn read(&self, keypair: &Keypair, limit: u64) -> Result<Vec<String>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        fs::read_to_string(&self.path)?
+            .lines()
+            .map(|line| {
+                let line = Sealed::from_str(line)?.open(keypair, limit)?;
+                String::from_utf8(line).context("Transcript is not valid UTF-8")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::{identity::Keypair, PeerId};
+
+    use super::Transcript;
+
+    #[test]
+    fn sealed_lines() -> Result<()> {
+        let dir = assert_fs::TempDir::new()?;
+        let keypair = Keypair::generate_ed25519();
+        let owner = keypair.public().to_peer_id();
+        let transcript = Transcript::new(dir.path(), &PeerId::random());
+        assert!(transcript.read(&keypair, u64::MAX)?.is_empty());
+
+        transcript.append("dante", "hi there", &owner)?;
+        transcript.append("You", "hello", &owner)?;
+        let saved = std::fs::read_to_string(&transcript.path)?;
+        assert!(!saved.contains("hi there"));
+
+        let lines = transcript.read(&keypair, u64::MAX)?;
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("] dante: hi there"));
+        assert!(transcript
+            .read(&Keypair::generate_ed25519(), u64::MAX)
+            .is_err());
+
+        dir.close()?;
+        Ok(())
+    }
+}
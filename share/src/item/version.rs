@@ -0,0 +1,239 @@
+//A key received again gets the new value, and the values it had are kept as its history, oldest
+//first, so they can be looked at and rolled back to.
+
+use std::mem;
+
+use anyhow::{anyhow, Result};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use super::Secret;
+
+/// Which value of its key a saved secret is, and who sent it when.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Version {
+    /// Counts the values received for the key, from 1
+    pub number: u32,
+    /// PeerId of the sender, unknown for secrets saved before versions were kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    /// Unix timestamp of when the value was received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<i64>,
+    /// The earlier version whose value this one restores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<u32>,
+}
+
+impl Version {
+    /// The version is numbered and timed by the receiver when it saves the secret.
+    pub fn sent_by(sender: &PeerId) -> Version {
+        Version {
+            sender: Some(sender.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+impl Secret {
+    /// 0 for secrets that have not been saved.
+    pub fn number(&self) -> u32 {
+        self.version.as_ref().map_or(0, |version| version.number)
+    }
+
+    /// The secret without its version and history, as it is read.
+    pub fn current(&self) -> Secret {
+        Secret {
+            version: None,
+            history: vec![],
+            ..self.clone()
+        }
+    }
+
+    /// Every value the secret had, oldest first, ending with the current one.
+    pub fn versions(&self) -> Vec<Secret> {
+        let mut versions = self.history.clone();
+        versions.push(Secret {
+            history: vec![],
+            ..self.clone()
+        });
+        versions
+    }
+}
+
+/// Saves `secret`, received at `now`, as the next version of its key in `saved`, keeping at most
+/// `limit` earlier values.
+pub(super) fn receive(saved: &mut Vec<Secret>, mut secret: Secret, now: i64, limit: usize) {
+    let number = next_number(saved, &secret.name());
+    let sender = secret.version.take().and_then(|version| version.sender);
+    secret.version = Some(Version {
+        number,
+        sender,
+        received_at: Some(now),
+        restored_from: None,
+    });
+    secret.history = vec![];
+    set_current(saved, secret, limit);
+}
+
+/// Makes the value version `to` of the secret `name` had, or else the one before its current
+/// value, its next version. Returns the secret restored.
+pub(super) fn restore(
+    saved: &mut Vec<Secret>,
+    name: &str,
+    to: Option<u32>,
+    now: i64,
+    limit: usize,
+) -> Result<Secret> {
+    let entry = saved
+        .iter()
+        .find(|entry| entry.name() == name)
+        .ok_or_else(|| anyhow!("No secret named {name} has been received"))?;
+    let earlier = match to {
+        Some(number) if number == entry.number() => {
+            return Err(anyhow!("v{number} is already the value of {name}"))
+        }
+        Some(number) => entry
+            .history
+            .iter()
+            .find(|version| version.number() == number)
+            .ok_or_else(|| anyhow!("{name} has no v{number}"))?,
+        None => entry
+            .history
+            .last()
+            .ok_or_else(|| anyhow!("{name} has no earlier value"))?,
+    };
+    if earlier.is_expired(now) {
+        return Err(anyhow!("v{} of {name} has expired", earlier.number()));
+    }
+
+    let restored = Secret {
+        version: Some(Version {
+            number: entry.number() + 1,
+            restored_from: Some(earlier.number()),
+            ..earlier.version.clone().unwrap_or_default()
+        }),
+        ..earlier.clone()
+    };
+    set_current(saved, restored.clone(), limit);
+    Ok(restored)
+}
+
+/// Keeps a single entry per key from files saved before versions were kept, where a key received
+/// again was added after the earlier one.
+pub(super) fn fold(saved: Vec<Secret>) -> Vec<Secret> {
+    let mut folded = vec![];
+    for mut secret in saved {
+        if secret.version.is_none() {
+            secret.version = Some(Version {
+                number: next_number(&folded, &secret.name()),
+                ..Default::default()
+            });
+        }
+        set_current(&mut folded, secret, usize::MAX);
+    }
+    folded
+}
+
+fn next_number(saved: &[Secret], name: &str) -> u32 {
+    saved
+        .iter()
+        .find(|entry| entry.name() == name)
+        .map_or(1, |entry| entry.number() + 1)
+}
+
+fn set_current(saved: &mut Vec<Secret>, secret: Secret, limit: usize) {
+    match saved.iter_mut().find(|entry| entry.name() == secret.name()) {
+        Some(entry) => {
+            let mut previous = mem::replace(entry, secret);
+            entry.history = mem::take(&mut previous.history);
+            entry.history.push(previous);
+            let dropped = entry.history.len().saturating_sub(limit);
+            entry.history.drain(..dropped);
+        }
+        None => saved.push(secret),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::PeerId;
+
+    use super::{fold, receive, restore, Version};
+    use crate::item::{Expiry, Secret};
+
+    fn numbers(secret: &Secret) -> Vec<u32> {
+        secret.versions().iter().map(Secret::number).collect()
+    }
+
+    #[test]
+    fn received_versions() {
+        let sender = PeerId::random();
+        let mut saved = vec![];
+        for (value, now) in [("a", 10), ("b", 20), ("c", 30)] {
+            let mut secret = Secret::new("API_KEY".into(), value.into());
+            secret.version = Some(Version::sent_by(&sender));
+            receive(&mut saved, secret, now, 1);
+        }
+        receive(&mut saved, Secret::new("PORT".into(), "80".into()), 40, 1);
+
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].value, "c");
+        assert_eq!(numbers(&saved[0]), vec![2, 3]);
+        let version = saved[0].version.clone().unwrap();
+        assert_eq!(version.sender, Some(sender.to_string()));
+        assert_eq!(version.received_at, Some(30));
+        assert_eq!(saved[0].history[0].value, "b");
+        assert_eq!(saved[0].current().version, None);
+        assert_eq!(numbers(&saved[1]), vec![1]);
+    }
+
+    #[test]
+    fn rolled_back() -> Result<()> {
+        let mut saved = vec![];
+        for value in ["a", "b", "c"] {
+            receive(
+                &mut saved,
+                Secret::new("API_KEY".into(), value.into()),
+                10,
+                5,
+            );
+        }
+
+        let restored = restore(&mut saved, "API_KEY", None, 20, 5)?;
+        assert_eq!(restored.value, "b");
+        assert_eq!(saved[0].value, "b");
+        let version = saved[0].version.clone().unwrap();
+        assert_eq!((version.number, version.restored_from), (4, Some(2)));
+        assert_eq!(version.received_at, Some(10));
+
+        assert_eq!(restore(&mut saved, "API_KEY", Some(1), 20, 5)?.value, "a");
+        assert_eq!(numbers(&saved[0]), vec![1, 2, 3, 4, 5]);
+        assert!(restore(&mut saved, "API_KEY", Some(5), 20, 5).is_err());
+        assert!(restore(&mut saved, "API_KEY", Some(9), 20, 5).is_err());
+        assert!(restore(&mut saved, "PORT", None, 20, 5).is_err());
+
+        let mut expired = Secret::new("TOKEN".into(), "old".into());
+        expired.expiry = Expiry::new(Some(60), false);
+        expired.expiry.as_mut().unwrap().start(0);
+        receive(&mut saved, expired, 10, 5);
+        receive(&mut saved, Secret::new("TOKEN".into(), "new".into()), 10, 5);
+        assert!(restore(&mut saved, "TOKEN", None, 100, 5).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn folded_duplicates() {
+        let saved = ["a", "b"]
+            .iter()
+            .map(|value| Secret::new("API_KEY".into(), value.to_string()))
+            .chain([Secret::new("PORT".into(), "80".into())])
+            .collect();
+        let folded = fold(saved);
+        assert_eq!(folded.len(), 2);
+        assert_eq!(folded[0].value, "b");
+        assert_eq!(numbers(&folded[0]), vec![1, 2]);
+        assert_eq!(fold(folded.clone()), folded);
+    }
+}
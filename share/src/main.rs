@@ -1,10 +1,18 @@
 use clap::Parser;
 use config::Config;
 use database::Store;
+use handlers::{
+    export::{export, ExportFormat},
+    history::{history, rollback},
+    read::read,
+    receipts::receipts,
+    verify::verify,
+};
+use item::Secret;
 use libp2p::PeerId;
 use network::punch;
 use std::{process::exit, str::FromStr};
-use tracing::error;
+use tracing::{debug, error};
 
 mod config;
 mod database;
@@ -19,22 +27,42 @@ mod network;
 #[command(version = "0.1.5")]
 #[command(about = "Share anything with teammates across machines via CLI.", long_about = None)]
 pub struct Cli {
-    /// Separated list of secrets to share. Key-Value pair is seperated by a comma. "my_key,my_value"
+    /// Separated list of secrets to share. Key-Value pair is seperated by the first `=` or comma.
+    /// "my_key=my_value" or "my_key,my_value". Values can be typed hidden at a prompt by passing
+    /// only "my_key"
     #[arg(long, short)]
     secret: Option<Vec<String>>,
 
+    /// Secrets to share whose values are read from a file, so they are not on the command line.
+    /// e,g --secret-file TLS_CERT=certs/server.pem
+    #[arg(long)]
+    secret_file: Option<Vec<String>>,
+
+    /// Secrets to share whose values are read from an environment variable.
+    /// e,g --secret-env API_TOKEN=API_TOKEN
+    #[arg(long)]
+    secret_env: Option<Vec<String>>,
+
+    /// Key of a secret to share whose value is read from stdin.
+    /// e,g vault read -field=password db | scs send -n dante --secret-stdin DB_PASSWORD
+    #[arg(long)]
+    secret_stdin: Option<String>,
+
     /// List of messages or a message string to deliver to the receiver.
     /// e,g -m "Hi there" -m "See me"
     #[arg(long, short)]
     message: Option<Vec<String>>,
 
-    /// List of file paths of files to deliver to the receiver.
-    /// e,g -f "/path/to/file1" -f "../path/to/file2"
+    /// List of file paths of files or directories to deliver to the receiver.
+    /// e,g -f "/path/to/file1" -f "../path/to/file2" -f "path/to/dir"
     #[arg(long, short)]
     file: Option<Vec<String>>,
 
-    /// The mode (send secrets, or receive secrets).
-    /// e,g `scs send` or `scs receive`
+    /// The mode (send secrets, receive secrets, request secrets from a peer, chat with a peer,
+    /// keep items for offline peers as a mailbox, list saved peers, read received items, export
+    /// received secrets, list or roll back the values received secrets had, verify who sent them
+    /// or list receipts of delivered items).
+    /// e,g `scs send` or `scs receive` or `scs request -s my_key` or `scs chat -n dante`
     mode: Mode,
 
     /// Peer ID of the remote to send secrets to.
@@ -56,13 +84,101 @@ pub struct Cli {
     /// Configuration file for `scs`
     #[arg(short, long)]
     config: Option<String>,
+
+    /// Save incoming items without being asked to accept them first
+    #[arg(long)]
+    accept_all: bool,
+
+    /// PeerId or address of a mailbox node to leave items on for a recipient that is offline,
+    /// or to collect the items left for you from
+    #[arg(long)]
+    mailbox: Option<String>,
+
+    /// Save the lines of a chat, which `scs read -n <name>` prints
+    #[arg(long)]
+    transcript: bool,
+
+    /// Stay connected after sending to send more items typed at a prompt, or to cancel items typed
+    /// at a prompt when receiving
+    #[arg(long, short)]
+    interactive: bool,
+
+    /// Only send the files of directories matching these globs.
+    /// e,g -f certs --include "*.pem"
+    #[arg(long)]
+    include: Option<Vec<String>>,
+
+    /// Leave out the files of directories matching these globs.
+    /// e,g -f config --exclude "*.local.yml"
+    #[arg(long)]
+    exclude: Option<Vec<String>>,
+
+    /// Send the secrets of a dotenv file, a flat JSON or YAML map or a Kubernetes `Secret`.
+    /// e,g --import .env --import k8s/secret.yml
+    #[arg(long)]
+    import: Option<Vec<String>>,
+
+    /// Only send these keys of the imported files.
+    /// e,g --import .env --only DATABASE_URL
+    #[arg(long)]
+    only: Option<Vec<String>>,
+
+    /// Leave out these keys of the imported files.
+    #[arg(long)]
+    except: Option<Vec<String>>,
+
+    /// Format `export` writes secrets in: dotenv (the default), shell, json, yaml or kubernetes.
+    /// e,g scs export --format kubernetes -o secret.yml
+    #[arg(long)]
+    format: Option<ExportFormat>,
+
+    /// File `export` writes to, only readable by you, or `read -f` writes the file to with the mode
+    /// it was sent with. Secrets and files are printed when left out.
+    /// e,g scs read -f deploy.sh -o deploy.sh
+    #[arg(long, short)]
+    output: Option<String>,
+
+    /// Only export the secrets received in this session, as `scs verify` shows it
+    #[arg(long)]
+    session: Option<String>,
+
+    /// Project secrets are sent in, or read and exported from.
+    /// e,g scs send -n dante --secret-file DATABASE_URL=url --project billing --env prod
+    #[arg(long)]
+    project: Option<String>,
+
+    /// Environment secrets are sent in, or read and exported from
+    #[arg(long)]
+    env: Option<String>,
+
+    /// How long the secrets sent can be read for once received, in seconds or e.g. "15m", "12h", "7d"
+    #[arg(long)]
+    expires_in: Option<String>,
+
+    /// Delete the secrets sent once they have been read
+    #[arg(long)]
+    burn: bool,
+
+    /// Version `rollback` restores, as `scs history` shows it. The one before the current value
+    /// when left out. e,g scs rollback -s DATABASE_URL --to 2
+    #[arg(long)]
+    to: Option<u32>,
 }
 
 #[derive(Clone, Debug, PartialEq, Parser, Copy)]
 pub enum Mode {
     Receive,
     Send,
+    Request,
+    Chat,
+    Mailbox,
     List,
+    Read,
+    Export,
+    History,
+    Rollback,
+    Verify,
+    Receipts,
 }
 
 impl FromStr for Mode {
@@ -71,8 +187,20 @@ impl FromStr for Mode {
         match mode {
             "send" => Ok(Mode::Send),
             "receive" => Ok(Mode::Receive),
+            "request" => Ok(Mode::Request),
+            "chat" => Ok(Mode::Chat),
+            "mailbox" => Ok(Mode::Mailbox),
             "list" => Ok(Mode::List),
-            _ => Err("Expected either 'send' or 'receive' or 'list'".to_string()),
+            "read" => Ok(Mode::Read),
+            "export" => Ok(Mode::Export),
+            "history" => Ok(Mode::History),
+            "rollback" => Ok(Mode::Rollback),
+            "verify" => Ok(Mode::Verify),
+            "receipts" => Ok(Mode::Receipts),
+            _ => Err(
+                "Expected either 'send' or 'receive' or 'request' or 'chat' or 'mailbox' or 'list' or 'read' or 'export' or 'history' or 'rollback' or 'verify' or 'receipts'"
+                    .to_string(),
+            ),
         }
     }
 }
@@ -98,14 +226,63 @@ async fn main() {
         }
     };
 
-    let code = {
-        match punch(mode, remote_peer_id, config, store) {
+    match Secret::purge_expired(&config.save_path()) {
+        Ok(0) => {}
+        //logged quietly, as `read` and `export` print to stdout
+        Ok(purged) => debug!("Deleted {purged} expired secrets"),
+        Err(err) => error!("Failed to delete expired secrets: {err}"),
+    }
+
+    let code = match mode {
+        Mode::Read => match read(&opts, &config, &store) {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        },
+        Mode::Export => match export(&opts, &config, &store) {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("{:#}", err);
+                1
+            }
+        },
+        Mode::History => match history(&opts, &config, &store) {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        },
+        Mode::Rollback => match rollback(&opts, &config) {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        },
+        Mode::Verify => match verify(&opts, &config, &store) {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        },
+        Mode::Receipts => match receipts(&opts, &store) {
+            Ok(_) => 0,
+            Err(err) => {
+                error!("{}", err);
+                1
+            }
+        },
+        _ => match punch(mode, remote_peer_id, config, store) {
             Ok(_) => 1,
             Err(err) => {
                 error!("{:#?}", err.to_string());
                 1
             }
-        }
+        },
     };
     ::std::process::exit(code);
 }
@@ -130,6 +307,9 @@ mod tests {
 
         let cli = Cli {
             secret,
+            secret_file: None,
+            secret_env: None,
+            secret_stdin: None,
             message,
             file,
             mode,
@@ -138,6 +318,23 @@ mod tests {
             port,
             config,
             name,
+            accept_all: false,
+            mailbox: None,
+            transcript: false,
+            interactive: false,
+            include: None,
+            exclude: None,
+            import: None,
+            only: None,
+            except: None,
+            format: None,
+            output: None,
+            session: None,
+            project: None,
+            env: None,
+            expires_in: None,
+            burn: false,
+            to: None,
         };
 
         assert_eq!(cli.debug, 0);
@@ -0,0 +1,202 @@
+//Either side can call off items still in flight, by name at the prompt of an interactive session
+//or all of them with Ctrl-C. The other side is told why, and both drop what is left of them.
+
+use std::{process::exit, thread};
+
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use libp2p::{PeerId, Swarm};
+use request_response::ResponseChannel;
+use tracing::{error, info};
+
+use super::{progress::Direction, request::stamp, session::Session, Behaviour, Request, Response};
+use crate::{
+    config::Config,
+    database::Store,
+    handlers::replay::check_stamp,
+    item::{Cancel, ItemFile, ItemType, ManifestEntry, ManifestResponse, Stamped},
+};
+
+/// Ctrl-C, once it is pressed. Pressing it again quits without waiting for the peers.
+pub fn interrupts() -> UnboundedReceiver<()> {
+    let (sender, receiver) = mpsc::unbounded();
+    thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                error!("Failed to listen for Ctrl-C: {err}");
+                return;
+            }
+        };
+        runtime.block_on(async {
+            let mut pressed = false;
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if pressed || sender.unbounded_send(()).is_err() {
+                    exit(130);
+                }
+                pressed = true;
+            }
+        });
+    });
+    receiver
+}
+
+/// Parses `cancel` or `cancel NAME`, giving the name of the item to cancel if there is one.
+pub fn typed_cancel(line: &str) -> Option<Option<String>> {
+    let line = line.trim();
+    let name = line.strip_prefix("cancel")?;
+    if name.is_empty() {
+        return Some(None);
+    }
+    if !name.starts_with(' ') {
+        return None;
+    }
+    Some(Some(name.trim().to_string()))
+}
+
+/// Cancels the item `name`, or everything, in flight with any peer. Returns the number of peers told.
+pub fn cancel_transfers(
+    name: Option<&str>,
+    swarm: &mut Swarm<Behaviour>,
+    config: &Config,
+    session: &mut Session,
+) -> usize {
+    let reason = format!("Cancelled by {}", swarm.local_peer_id());
+    let mut cancelled = 0;
+    for peer in session.active_peers() {
+        let cancel = match (name, session.in_flight(&peer)) {
+            (None, _) => Cancel::all(&reason),
+            (Some(name), Some(manifest)) => match Cancel::named(&manifest, name, &reason) {
+                Some(cancel) => cancel,
+                None => continue,
+            },
+            (Some(_), None) => continue,
+        };
+
+        info!("Cancelling {} with {peer}", describe(&cancel));
+        cancel_locally(peer, cancel.clone(), config, session);
+        cancelled += 1;
+        if let Some(request) = stamp(cancel, config, &peer) {
+            session.await_cancel(peer);
+            swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(&peer, Request::Cancel(request));
+        }
+    }
+    cancelled
+}
+
+/// Cancels what the line typed names, e.g. `cancel report.pdf`.
+pub fn cancel_typed(
+    name: Option<String>,
+    swarm: &mut Swarm<Behaviour>,
+    config: &Config,
+    session: &mut Session,
+) {
+    if cancel_transfers(name.as_deref(), swarm, config, session) == 0 {
+        match name {
+            Some(name) => error!("No item named {name} is in flight"),
+            None => error!("No items are in flight"),
+        }
+    }
+}
+
+/// Stops the items `peer` cancelled and acknowledges it.
+pub fn handle_cancel(
+    request: Stamped<Cancel>,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    store: &Store,
+    session: &mut Session,
+) {
+    let local_peer_id = *swarm.local_peer_id();
+    let res = match check_stamp(&request, peer, local_peer_id, config, store) {
+        Ok(_) => {
+            let cancel = request.content;
+            info!(
+                "{peer} cancelled {}: {}",
+                describe(&cancel),
+                cancel.reason()
+            );
+            cancel_locally(peer, cancel, config, session);
+            ManifestResponse::accepted()
+        }
+        Err(err) => {
+            error!("Refused the cancellation from {peer}: {err}");
+            ManifestResponse::declined(&format!("The cancellation was refused: {err}"))
+        }
+    };
+
+    let sent = swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, Response::Cancel(res));
+    if sent.is_err() {
+        error!("{peer} stopped waiting for an answer to its cancellation");
+    }
+}
+
+pub fn handle_cancel_response(response: ManifestResponse, peer: PeerId, session: &mut Session) {
+    session.cancel_answered(&peer);
+    if !response.accepted {
+        let reason = response.reason.unwrap_or_default();
+        error!("{peer} did not take the cancellation: {reason}");
+    }
+}
+
+fn cancel_locally(peer: PeerId, cancel: Cancel, config: &Config, session: &mut Session) {
+    let direction = match session.is_pending(&peer) {
+        true => Direction::Upload,
+        false => Direction::Download,
+    };
+    if cancel.is_all() {
+        session.progress().cancel(direction);
+    }
+
+    let entries = session.cancel(peer, cancel);
+    if direction == Direction::Download {
+        remove_partials(&entries, config);
+    }
+}
+
+/// Removes what was written of the cancelled files.
+fn remove_partials(entries: &[ManifestEntry], config: &Config) {
+    let save_path = config.save_path();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.item_type == ItemType::File)
+    {
+        if let Err(err) = ItemFile::remove_partial(&save_path, &entry.name) {
+            error!("Failed to remove what was saved of {}: {err}", entry.name);
+        }
+    }
+}
+
+fn describe(cancel: &Cancel) -> String {
+    match cancel.digests.len() {
+        0 => "every item".to_string(),
+        1 => "1 item".to_string(),
+        count => format!("{count} items"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::typed_cancel;
+
+    #[test]
+    fn typed_cancels() {
+        assert_eq!(typed_cancel("cancel"), Some(None));
+        assert_eq!(
+            typed_cancel(" cancel report.pdf "),
+            Some(Some("report.pdf".to_string()))
+        );
+        assert_eq!(typed_cancel("cancelled"), None);
+        assert_eq!(typed_cancel("message cancel"), None);
+    }
+}
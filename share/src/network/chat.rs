@@ -0,0 +1,143 @@
+//Chats send the lines typed on stdin to the connected peer as message items, sealed and signed
+//like any other item, and print the ones the peer sends with its saved name.
+
+use std::{
+    io::{self, BufRead},
+    thread,
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use libp2p::{PeerId, Swarm};
+use request_response::ResponseChannel;
+use tracing::error;
+
+use super::{
+    request::{compresses, stamp},
+    session::Session,
+    Behaviour, Request, Response,
+};
+use crate::{
+    config::Config,
+    database::{peer::ScsPeer, Store},
+    handlers::replay::check_stamp,
+    item::{Item, ItemType, ManifestResponse, Stamped, Transcript},
+};
+
+/// Reads the lines typed on stdin on their own thread. The stream ends when stdin is closed.
+pub fn typed_lines() -> UnboundedReceiver<String> {
+    let (sender, receiver) = mpsc::unbounded();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.unbounded_send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// The saved name of `peer`, or its PeerId.
+fn display_name(peer: PeerId, store: &Store) -> String {
+    match ScsPeer::get_by_peer_id(peer.to_string(), store) {
+        Ok(Some(saved)) => saved.name(),
+        _ => peer.to_string(),
+    }
+}
+
+/// Sends a typed line to the peer in the chat.
+pub fn send_line(line: String, swarm: &mut Swarm<Behaviour>, config: &Config, session: &Session) {
+    let peer = match session.chat_peer() {
+        Some(peer) => peer,
+        None => {
+            error!("Nobody has joined the chat yet");
+            return;
+        }
+    };
+    if line.trim().is_empty() {
+        return;
+    }
+
+    let mut item = Item::new(line.clone(), ItemType::Message).unwrap();
+    let sealed = item
+        .seal(&peer, compresses(config, session, &peer))
+        .and_then(|_| item.sign(&config.keypair(), &peer));
+    if let Err(err) = sealed {
+        error!("Failed to seal the message for {peer}: {err}");
+        return;
+    }
+    let request = match stamp(item, config, &peer) {
+        Some(request) => request,
+        None => return,
+    };
+    swarm
+        .behaviour_mut()
+        .request_response
+        .send_request(&peer, Request::Chat(request));
+
+    if config.transcript() {
+        save_line(config, peer, "You", &line);
+    }
+}
+
+/// Prints a line sent by `peer`, if it is the peer in the chat.
+pub fn handle_chat(
+    request: Stamped<Item>,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    store: &Store,
+    session: &mut Session,
+) {
+    let local_peer_id = *swarm.local_peer_id();
+    let res = if session.join_chat(peer) {
+        let line = check_stamp(&request, peer, local_peer_id, config, store)
+            .and_then(|_| open_line(&request.content, config, peer, local_peer_id));
+        match line {
+            Ok(line) => {
+                let name = display_name(peer, store);
+                println!("{name}: {line}");
+                if config.transcript() {
+                    save_line(config, peer, &name, &line);
+                }
+                ManifestResponse::accepted()
+            }
+            Err(err) => {
+                error!("Failed to read the message from {peer}: {err}");
+                ManifestResponse::declined(&err.to_string())
+            }
+        }
+    } else {
+        ManifestResponse::declined("Not chatting with you")
+    };
+
+    let sent = swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, Response::Chat(res));
+    if sent.is_err() {
+        error!("{peer} stopped waiting for an answer to its message");
+    }
+}
+
+fn open_line(item: &Item, config: &Config, sender: PeerId, recipient: PeerId) -> Result<String> {
+    item.verify(&sender, &recipient)?;
+    if item.item_type() != ItemType::Message {
+        return Err(anyhow!("Only messages can be sent in a chat"));
+    }
+    let line = item.open(
+        &config.keypair(),
+        config.max_opened_size(),
+        config.compression(),
+    )?;
+    String::from_utf8(line).context("Message is not valid UTF-8")
+}
+
+fn save_line(config: &Config, peer: PeerId, speaker: &str, line: &str) {
+    let owner = config.keypair().public().to_peer_id();
+    let saved = Transcript::new(&config.save_path(), &peer).append(speaker, line, &owner);
+    if let Err(err) = saved {
+        error!("Failed to save the chat: {err}");
+    }
+}
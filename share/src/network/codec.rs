@@ -0,0 +1,457 @@
+//Encodes items on the request-response protocol.
+//CBOR is offered first so that peers which both support it use it, with JSON as the fallback.
+//The protocol names carry the version of the requests and responses, so a peer running an `scs`
+//with another format fails to negotiate instead of sending what the other side can't decode.
+//Payloads are compressed before they are sealed, as ciphertext does not compress, so bodies are
+//sent as they are. A node with compression on also offers a CBOR protocol marked `+zstd`, which
+//is encoded the same way: peers that list it take compressed payloads, and the others get them
+//uncompressed.
+
+use std::io;
+
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::StreamProtocol;
+use request_response::ProtocolSupport;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    progress::{Direction, Progress},
+    Request, Response,
+};
+
+pub const CBOR_ZSTD_PROTOCOL: StreamProtocol = StreamProtocol::new("/share/2/cbor+zstd");
+pub const CBOR_PROTOCOL: StreamProtocol = StreamProtocol::new("/share/2/cbor");
+pub const JSON_PROTOCOL: StreamProtocol = StreamProtocol::new("/share/2/json");
+
+/// Max request size in bytes
+const REQUEST_SIZE_MAXIMUM: u64 = 256 * 1024 * 1024;
+/// Max response size in bytes. Deliveries collected from a mailbox come in a response.
+const RESPONSE_SIZE_MAXIMUM: u64 = REQUEST_SIZE_MAXIMUM;
+/// Most deliveries, as kept by a mailbox in CBOR, handed over in one collection. JSON takes up
+/// to four bytes for each byte of payload.
+pub const COLLECTION_SIZE_MAXIMUM: u64 = RESPONSE_SIZE_MAXIMUM / 4;
+/// Size of the pieces bodies are written and read in, to report progress
+const PIECE_SIZE: usize = 64 * 1024;
+/// How a delivery of items starts in CBOR: a map with the single key "Items"
+const CBOR_ITEMS: &[u8] = b"\xa1\x65Items";
+/// How deliveries collected from a mailbox start in CBOR
+const CBOR_COLLECT: &[u8] = b"\xa1\x67Collect";
+
+/// The protocols supported by `scs`, in order of preference.
+pub fn protocols(compression: bool) -> Vec<(StreamProtocol, ProtocolSupport)> {
+    let mut protocols = vec![];
+    if compression {
+        protocols.push((CBOR_ZSTD_PROTOCOL, ProtocolSupport::Full));
+    }
+    protocols.push((CBOR_PROTOCOL, ProtocolSupport::Full));
+    protocols.push((JSON_PROTOCOL, ProtocolSupport::Full));
+    protocols
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Json,
+    Cbor,
+}
+
+impl From<&StreamProtocol> for Encoding {
+    fn from(protocol: &StreamProtocol) -> Self {
+        if protocol == &JSON_PROTOCOL {
+            Encoding::Json
+        } else {
+            Encoding::Cbor
+        }
+    }
+}
+
+impl Encoding {
+    pub fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(value)?),
+            Encoding::Cbor => serde_cbor::to_vec(value).map_err(into_io_error),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> io::Result<T> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(data)?),
+            Encoding::Cbor => serde_cbor::from_slice(data).map_err(into_io_error),
+        }
+    }
+}
+
+fn into_io_error(err: serde_cbor::Error) -> io::Error {
+    if err.is_syntax() || err.is_data() {
+        return io::Error::new(io::ErrorKind::InvalidData, err);
+    }
+
+    if err.is_eof() {
+        return io::Error::new(io::ErrorKind::UnexpectedEof, err);
+    }
+
+    io::Error::other(err)
+}
+
+/// Reads a whole body. If it starts with `tag`, the bytes read so far are reported as a download.
+/// Payloads are byte strings in CBOR, so the bytes read follow the payload bytes, give or take a
+/// few hundred per item. JSON writes them as arrays of numbers of no set length, so no tag is
+/// given and nothing is reported for it.
+async fn read_body<T>(
+    io: &mut T,
+    limit: u64,
+    tag: Option<&[u8]>,
+    progress: &Progress,
+) -> io::Result<Vec<u8>>
+where
+    T: AsyncRead + Unpin + Send,
+{
+    let mut io = io.take(limit);
+    let mut data = Vec::new();
+    let mut piece = vec![0u8; PIECE_SIZE];
+    loop {
+        let read = io.read(&mut piece).await?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&piece[..read]);
+        if tag.is_some_and(|tag| data.starts_with(tag)) {
+            progress.advance(Direction::Download, data.len() as u64);
+        }
+    }
+    Ok(data)
+}
+
+/// Writes a whole body, calling `report` with the number of bytes of `data` written so far.
+async fn write_body<T>(io: &mut T, data: Vec<u8>, report: impl Fn(u64)) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+{
+    let mut written = 0;
+    for piece in data.chunks(PIECE_SIZE) {
+        io.write_all(piece).await?;
+        written += piece.len();
+        report(written as u64);
+    }
+    Ok(())
+}
+
+/// Reports the progress of item requests and mailbox collections, which are the only bodies
+/// large enough to take a while, in bytes of the payloads they carry. Bodies received in JSON are
+/// only reported once they have arrived.
+#[derive(Debug, Clone, Default)]
+pub struct ItemCodec {
+    progress: Progress,
+}
+
+impl ItemCodec {
+    pub fn new(progress: Progress) -> ItemCodec {
+        ItemCodec { progress }
+    }
+}
+
+#[async_trait]
+impl request_response::Codec for ItemCodec {
+    type Protocol = StreamProtocol;
+    type Request = Request;
+    type Response = Response;
+
+    async fn read_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let encoding = Encoding::from(protocol);
+        let tag = (encoding == Encoding::Cbor).then_some(CBOR_ITEMS);
+        let data = read_body(io, REQUEST_SIZE_MAXIMUM, tag, &self.progress).await?;
+        encoding.decode(&data)
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let encoding = Encoding::from(protocol);
+        let tag = (encoding == Encoding::Cbor).then_some(CBOR_COLLECT);
+        let data = read_body(io, RESPONSE_SIZE_MAXIMUM, tag, &self.progress).await?;
+        encoding.decode(&data)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        req: Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let payload = match &req {
+            Request::Items(delivery) => delivery
+                .items
+                .iter()
+                .map(|item| item.payload().len() as u64)
+                .sum(),
+            _ => 0,
+        };
+        let data = Encoding::from(protocol).encode(&req)?;
+        let encoded = data.len().max(1) as u64;
+        let progress = &self.progress;
+        write_body(io, data, |bytes| {
+            if payload > 0 {
+                progress.advance(Direction::Upload, bytes * payload / encoded)
+            }
+        })
+        .await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        res: Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = Encoding::from(protocol).encode(&res)?;
+        write_body(io, data, |_| {}).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use assert_fs::prelude::FileWriteBin;
+    use futures::io::Cursor;
+    use libp2p::{identity::Keypair, PeerId};
+    use request_response::Codec;
+
+    use super::{
+        protocols, Encoding, ItemCodec, CBOR_COLLECT, CBOR_ITEMS, CBOR_PROTOCOL,
+        CBOR_ZSTD_PROTOCOL, JSON_PROTOCOL,
+    };
+    use crate::{
+        item::{
+            Cancel, Collection, Delivery, Deposit, Failure, Item, ItemResponse, ItemType, Manifest,
+            Stamped, Status,
+        },
+        network::{Request, Response},
+    };
+
+    fn make_items() -> Result<(Vec<Item>, assert_fs::NamedTempFile)> {
+        let file = assert_fs::NamedTempFile::new("payload.bin")?;
+        let data = (0..64 * 1024)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect::<Vec<_>>();
+        file.write_binary(&data)?;
+
+        let items = vec![
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+            Item::new("hi there".to_string(), ItemType::Message)?,
+            Item::new(file.path().to_str().unwrap().to_string(), ItemType::File)?,
+        ];
+        Ok((items, file))
+    }
+
+    #[tokio::test]
+    async fn round_trip() -> Result<()> {
+        let (items, file) = make_items()?;
+        let keypair = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+
+        for protocol in [CBOR_PROTOCOL, JSON_PROTOCOL] {
+            let mut codec = ItemCodec::default();
+            for request in [
+                Request::Manifest(Stamped::new(Manifest::new(&items), &keypair, &recipient)?),
+                Request::Items(Delivery::new(items.clone(), &keypair, &recipient)?),
+                Request::Pull(Stamped::new(
+                    vec!["STRIPE_KEY".to_string()],
+                    &keypair,
+                    &recipient,
+                )?),
+                Request::Deposit(Deposit::new(
+                    keypair.public().to_peer_id(),
+                    recipient,
+                    Delivery::new(items.clone(), &keypair, &recipient)?,
+                )),
+                Request::Collect(Stamped::new(vec![vec![7; 16]], &keypair, &recipient)?),
+                Request::Cancel(Stamped::new(Cancel::all("closing"), &keypair, &recipient)?),
+            ] {
+                let mut io = Cursor::new(Vec::new());
+                codec
+                    .write_request(&protocol, &mut io, request.clone())
+                    .await?;
+                io.set_position(0);
+                assert_eq!(codec.read_request(&protocol, &mut io).await?, request);
+            }
+
+            let response = ItemResponse {
+                status: Status::Succes,
+                no_of_success: 2,
+                no_of_fails: 1,
+                no_of_cancelled: 0,
+                err: None,
+                acks: vec![],
+                failed: vec![Failure {
+                    digest: items[2].digest(),
+                    reason: "No space left".to_string(),
+                }],
+            };
+            let mut io = Cursor::new(Vec::new());
+            codec
+                .write_response(&protocol, &mut io, Response::Items(response))
+                .await?;
+            io.set_position(0);
+            match codec.read_response(&protocol, &mut io).await? {
+                Response::Items(response) => {
+                    assert_eq!(response.no_of_success, 2);
+                    assert_eq!(response.failed[0].digest, items[2].digest());
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+
+        file.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn encoding_from_protocol() {
+        assert_eq!(Encoding::from(&CBOR_PROTOCOL), Encoding::Cbor);
+        assert_eq!(Encoding::from(&JSON_PROTOCOL), Encoding::Json);
+        assert_eq!(Encoding::from(&CBOR_ZSTD_PROTOCOL), Encoding::Cbor);
+    }
+
+    #[test]
+    fn compressed_protocol_offered() {
+        let offered = |compression| {
+            protocols(compression)
+                .into_iter()
+                .map(|(protocol, _)| protocol)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            offered(true),
+            vec![CBOR_ZSTD_PROTOCOL, CBOR_PROTOCOL, JSON_PROTOCOL]
+        );
+        assert_eq!(offered(false), vec![CBOR_PROTOCOL, JSON_PROTOCOL]);
+    }
+
+    #[test]
+    fn payload_sizes() -> Result<()> {
+        let (items, file) = make_items()?;
+
+        let raw = 64 * 1024;
+        let json = Encoding::Json.encode(&items)?.len();
+        let cbor = Encoding::Cbor.encode(&items)?.len();
+        let delivery = Request::Items(Delivery::new(
+            items,
+            &Keypair::generate_ed25519(),
+            &PeerId::random(),
+        )?);
+        assert!(Encoding::Cbor.encode(&delivery)?.starts_with(CBOR_ITEMS));
+        let collection = Response::Collect(Collection {
+            deposits: vec![],
+            err: None,
+        });
+        assert!(Encoding::Cbor
+            .encode(&collection)?
+            .starts_with(CBOR_COLLECT));
+
+        assert!(json > raw * 3);
+        assert!(cbor < raw + 1024);
+
+        file.close()?;
+        Ok(())
+    }
+
+    /// Prints the size on the wire of a delivery of each kind of item in each encoding, as the
+    /// README reports them: `cargo test encoding_sizes -- --nocapture`.
+    #[test]
+    fn encoding_sizes() -> Result<()> {
+        use rand::RngCore;
+
+        let dir = assert_fs::TempDir::new()?;
+        let config = "port: 5555\nsave_path: /var/lib/scs\ndebug: 0\n".repeat(40);
+        let log = (0..16 * 1024)
+            .map(|i| {
+                format!(
+                    "2023-08-01T12:{:02}:{:02}Z INFO GET /api/items/{} 200 {}ms\n",
+                    i / 60 % 60,
+                    i % 60,
+                    i * 7919 % 100_000,
+                    i * 31 % 500
+                )
+            })
+            .collect::<String>();
+        let mut random = vec![0u8; 1024 * 1024];
+        rand::thread_rng().fill_bytes(&mut random);
+        let files: [(&str, &[u8]); 3] = [
+            ("config.yml", config.as_bytes()),
+            ("server.log", log.as_bytes()),
+            ("random.bin", &random),
+        ];
+        let mut items = vec![
+            (
+                "secret",
+                Item::new(
+                    "DATABASE_URL,postgres://app:hunter2@db:5432/app".to_string(),
+                    ItemType::Secret,
+                )?,
+            ),
+            (
+                "message",
+                Item::new(
+                    "The staging database moves tonight".to_string(),
+                    ItemType::Message,
+                )?,
+            ),
+        ];
+        for (name, data) in files {
+            let file = assert_fs::prelude::PathChild::child(&dir, name);
+            file.write_binary(data)?;
+            let path = file.path().to_str().unwrap().to_string();
+            items.push((name, Item::new(path, ItemType::File)?));
+        }
+
+        let keypair = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().public().to_peer_id();
+        let size = |item: &Item, compress: bool, encoding: Encoding| -> Result<usize> {
+            let mut item = item.clone();
+            item.seal(&recipient, compress)?;
+            let delivery = Delivery::new(vec![item], &keypair, &recipient)?;
+            Ok(encoding.encode(&Request::Items(delivery))?.len())
+        };
+
+        println!(
+            "{:<12} {:>10} {:>10} {:>10} {:>10}",
+            "item", "payload", "cbor", "cbor+zstd", "json"
+        );
+        for (name, item) in &items {
+            let cbor = size(item, false, Encoding::Cbor)?;
+            let zstd = size(item, true, Encoding::Cbor)?;
+            let json = size(item, false, Encoding::Json)?;
+            let payload = item.payload().len();
+            println!("{name:<12} {payload:>10} {cbor:>10} {zstd:>10} {json:>10}");
+
+            assert!(cbor < json);
+            assert!(cbor < payload + 1024);
+            assert!(zstd < cbor + 64);
+        }
+        let compressible = &items[3].1;
+        assert!(size(compressible, true, Encoding::Cbor)? * 10 < compressible.payload().len());
+        let incompressible = &items[4].1;
+        assert!(size(incompressible, false, Encoding::Json)? > incompressible.payload().len() * 3);
+
+        dir.close()?;
+        Ok(())
+    }
+}
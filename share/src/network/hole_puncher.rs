@@ -1,26 +1,31 @@
 //This "Direct Connection Upgrade Through Relay Server" (DCUTR) allows peers to establish direct connections with each other.
 //i.e hole punching
 
-use std::process::exit;
+use std::{process::exit, time::Duration};
 
-use super::request_response_handler;
+use super::{handle_answer, prompts, request_response_handler};
 use crate::database::Store;
 use crate::handlers::security::{is_ip_blacklisted, is_ip_whitelisted};
-use crate::network::request::make_request;
-use crate::network::{get_behaviour, ConnectionDetails, Event};
+use crate::network::cancel::{self, cancel_transfers, cancel_typed, typed_cancel};
+use crate::network::chat::{self, send_line};
+use crate::network::request::{make_request, send_typed};
+use crate::network::{
+    codec::CBOR_ZSTD_PROTOCOL, get_behaviour, progress::Progress, session::Session,
+    ConnectionDetails, Event,
+};
 use crate::{config::Config, Mode};
 use anyhow::Result;
-use futures::future::Either;
+use futures::future::{Either, Fuse};
 use futures::{
     executor::{block_on, ThreadPool},
-    stream::StreamExt,
+    stream::{self, StreamExt},
     FutureExt,
 };
 use libp2p::tls;
 use libp2p::{
     core::{muxing::StreamMuxerBox, upgrade},
     dns::DnsConfig,
-    identify, identity,
+    identify,
     multiaddr::Protocol,
     relay,
     swarm::{SwarmBuilder, SwarmEvent},
@@ -40,10 +45,9 @@ pub fn punch(
             .to_string()
             .parse()
             .unwrap();
-    let secret_key_seed = config.seed_key();
     let port = config.port();
 
-    let local_key = generate_ed25519(secret_key_seed);
+    let local_key = config.keypair();
     let local_peer_id = PeerId::from(local_key.public());
     info!("Your PeerId is: {}", local_peer_id);
 
@@ -71,7 +75,15 @@ pub fn punch(
             .boxed()
     };
 
-    let behaviour = get_behaviour((client, local_key, local_peer_id, mode));
+    let progress = Progress::new();
+    let behaviour = get_behaviour((
+        client,
+        local_key,
+        local_peer_id,
+        mode,
+        progress.clone(),
+        config.compression(),
+    ));
     let mut swarm = match ThreadPool::new() {
         Ok(tp) => SwarmBuilder::with_executor(transport, behaviour, local_peer_id, tp),
         Err(_) => SwarmBuilder::without_executor(transport, behaviour, local_peer_id),
@@ -126,7 +138,7 @@ pub fn punch(
                 SwarmEvent::NewListenAddr { .. } => {}
                 SwarmEvent::Dialing { .. } => {}
                 SwarmEvent::ConnectionEstablished { .. } => {}
-                SwarmEvent::Behaviour(Event::Ping(_)) => {}
+                SwarmEvent::Behaviour(Event::Ping) => {}
                 SwarmEvent::Behaviour(Event::Identify(identify::Event::Sent { .. })) => {
                     debug!("Told relay its public address.");
                     told_relay_observed_addr = true;
@@ -148,9 +160,25 @@ pub fn punch(
         }
     });
 
+    let relay_peer_id = match relay_address.iter().last() {
+        Some(Protocol::P2p(peer_id)) => Some(peer_id),
+        _ => None,
+    };
+    let mut session = Session::new(progress);
+    let mailbox = match mode {
+        Mode::Send | Mode::Receive => config.mailbox().peer(&relay_address)?,
+        _ => None,
+    };
+    if let Some((mailbox_peer_id, _)) = &mailbox {
+        session.set_mailbox(*mailbox_peer_id);
+    }
+
     //denotes whether to send or receive secrets
-    match mode {
-        Mode::Send => {
+    match (mode, mailbox) {
+        (Mode::Send, Some((_, mailbox_address))) => {
+            swarm.dial(mailbox_address).unwrap();
+        }
+        (Mode::Send | Mode::Request | Mode::Chat, _) if remote_peer_id.is_some() => {
             swarm
                 .dial(
                     relay_address
@@ -159,25 +187,111 @@ pub fn punch(
                 )
                 .unwrap();
         }
-        Mode::Receive => {
+        (Mode::Receive, mailbox) => {
+            swarm
+                .listen_on(relay_address.with(Protocol::P2pCircuit))
+                .unwrap();
+            if let Some((_, mailbox_address)) = mailbox {
+                swarm.dial(mailbox_address).unwrap();
+            }
+        }
+        (Mode::Mailbox, _) => {
             swarm
                 .listen_on(relay_address.with(Protocol::P2pCircuit))
                 .unwrap();
+            info!("Keeping items for peers that are offline");
+        }
+        (Mode::Chat, _) => {
+            swarm
+                .listen_on(relay_address.with(Protocol::P2pCircuit))
+                .unwrap();
+            info!("Waiting for a peer to join the chat");
         }
         _ => {}
     }
+    if mode == Mode::Chat {
+        session.open_chat(remote_peer_id);
+    }
+    let mut lines = match mode {
+        Mode::Chat => chat::typed_lines().boxed(),
+        Mode::Send | Mode::Receive if config.interactive() => chat::typed_lines().boxed(),
+        _ => stream::pending().boxed(),
+    }
+    .fuse();
+    let mut interrupts = match mode {
+        Mode::Send | Mode::Receive => cancel::interrupts().boxed(),
+        _ => stream::pending().boxed(),
+    }
+    .fuse();
+    //how long to wait for the peers to acknowledge the cancellations when closing
+    let mut closing = Fuse::<futures_timer::Delay>::terminated();
+    let (questions, mut answers) = prompts();
     let mut connection_deets = ConnectionDetails::new();
 
     block_on(async {
         loop {
-            match swarm.next().await.unwrap() {
+            for question in session.take_questions() {
+                if questions.send(question).is_err() {
+                    error!("Failed to ask for approval");
+                }
+            }
+            let event = futures::select! {
+                event = swarm.select_next_some() => event,
+                answer = answers.select_next_some() => {
+                    handle_answer(answer, &config, &mut swarm, &store, &mut session);
+                    continue;
+                }
+                line = lines.next() => {
+                    let cancelled = line
+                        .as_deref()
+                        .filter(|_| mode != Mode::Chat)
+                        .and_then(typed_cancel);
+                    match (line, remote_peer_id) {
+                        (Some(_), _) if cancelled.is_some() => {
+                            cancel_typed(cancelled.unwrap(), &mut swarm, &config, &mut session)
+                        }
+                        (Some(_), _) if mode == Mode::Receive => {
+                            error!("Type `cancel NAME` to stop an item being received, or `cancel` for all of them")
+                        }
+                        (Some(line), Some(recipient)) if mode == Mode::Send => {
+                            send_typed(line, recipient, &mut swarm, &config, &mut session)
+                        }
+                        (Some(line), _) => send_line(line, &mut swarm, &config, &session),
+                        (None, _) if mode == Mode::Chat => {
+                            info!("Left the chat");
+                            exit(0);
+                        }
+                        (None, _) => {
+                            info!("Closing the session");
+                            exit(0);
+                        }
+                    }
+                    continue;
+                }
+                _ = interrupts.next() => {
+                    let peers = cancel_transfers(None, &mut swarm, &config, &mut session);
+                    if peers == 0 {
+                        exit(130);
+                    }
+                    info!("Cancelling the transfers with {peers} peers. Press Ctrl-C again to quit now");
+                    session.close();
+                    closing = futures_timer::Delay::new(Duration::from_secs(3)).fuse();
+                    continue;
+                }
+                _ = closing => {
+                    error!("Closing without every peer acknowledging the cancellation");
+                    exit(130);
+                }
+            };
+
+            match event {
                 SwarmEvent::NewListenAddr { address, .. } => {
                     info!("Listening on {:?}", address);
                 }
                 SwarmEvent::Behaviour(Event::Relay(
                     relay::client::Event::ReservationReqAccepted { .. },
                 )) => {
-                    assert!(mode == Mode::Receive);
+                    assert!(matches!(mode, Mode::Receive | Mode::Mailbox | Mode::Chat));
                     debug!("Relay accepted our reservation request.");
                 }
                 SwarmEvent::Behaviour(Event::Relay(event)) => {
@@ -202,8 +316,26 @@ pub fn punch(
                         swarm.close_connection(connection_id);
                         exit(1);
                     }
+
+                    if let identify::Event::Received { peer_id, info } = event {
+                        if Some(peer_id) == relay_peer_id {
+                            continue;
+                        }
+                        let compressed = info.protocols.contains(&CBOR_ZSTD_PROTOCOL);
+                        session.identify(peer_id, compressed);
+
+                        //items are only sealed once we know whether the peer takes them compressed
+                        make_request(
+                            mode,
+                            &mut swarm,
+                            peer_id,
+                            remote_peer_id,
+                            &config,
+                            &mut session,
+                        );
+                    }
                 }
-                SwarmEvent::Behaviour(Event::Ping(_)) => {}
+                SwarmEvent::Behaviour(Event::Ping) => {}
                 SwarmEvent::IncomingConnection { connection_id, .. } => {
                     debug!("INCOMING CONNECTION: {:?}", connection_id);
                 }
@@ -217,9 +349,19 @@ pub fn punch(
 
                     let addr = endpoint.get_remote_address();
                     info!("Established connection to {peer_id} via {addr}");
+                    if Some(peer_id) == relay_peer_id {
+                        continue;
+                    }
 
                     //Send secrets to the receiver
-                    make_request(mode, &mut swarm, peer_id, &config);
+                    make_request(
+                        mode,
+                        &mut swarm,
+                        peer_id,
+                        remote_peer_id,
+                        &config,
+                        &mut session,
+                    );
                 }
                 SwarmEvent::OutgoingConnectionError {
                     peer_id: _, error, ..
@@ -229,7 +371,28 @@ pub fn punch(
                 SwarmEvent::Behaviour(Event::RequestResonse(
                     request_response::Event::Message { peer, message },
                 )) => {
-                    request_response_handler(&mut swarm, message, peer, &config);
+                    request_response_handler(
+                        &mut swarm,
+                        message,
+                        peer,
+                        mode,
+                        &config,
+                        &store,
+                        &mut session,
+                    );
+                    if session.is_closed() {
+                        info!("Cancelled the transfers, closing the session");
+                        exit(130);
+                    }
+                    //a mailbox only passes items on, and chats and interactive sends keep stdin
+                    //for the lines typed. Peers are not saved while the owner is asked to approve items
+                    if matches!(mode, Mode::Mailbox | Mode::Chat)
+                        || session.is_mailbox(&peer)
+                        || session.is_asking()
+                        || config.interactive()
+                    {
+                        continue;
+                    }
                     match store.store_peer(&mut swarm, peer) {
                         Ok(_) => {}
                         Err(err) => {
@@ -242,8 +405,3 @@ pub fn punch(
         }
     })
 }
-
-fn generate_ed25519(mut secret_key_seed: String) -> identity::Keypair {
-    let bytes = unsafe { secret_key_seed.as_bytes_mut() };
-    identity::Keypair::ed25519_from_bytes(bytes).expect("only errors on wrong length")
-}
@@ -1,23 +1,85 @@
 use std::time::Duration;
 
+use anyhow::anyhow;
 use libp2p::{
     autonat, dcutr, identify, ping, relay,
     swarm::{ConnectionId, NetworkBehaviour},
-    PeerId, StreamProtocol, Swarm,
+    PeerId, Swarm,
 };
-use request_response::{json, Message, ProtocolSupport};
+use request_response::Message;
+use serde::{Deserialize, Serialize};
 use tracing::{error, info};
 
 use crate::{
     config::Config,
-    item::{Item, ItemResponse},
+    database::Store,
+    handlers::replay::check_replay,
+    item::{
+        Cancel, Collection, Delivery, Deposit, Item, ItemResponse, Manifest, ManifestResponse,
+        Stamped, Status,
+    },
     Mode,
 };
+use cancel::{handle_cancel, handle_cancel_response};
+use chat::handle_chat;
+use codec::ItemCodec;
 pub use hole_puncher::punch;
-use request::handle_request;
+use progress::{Direction, Progress};
+use prompt::{handle_answer, prompts};
+use request::{
+    handle_collect, handle_collection, handle_deposit, handle_item_response, handle_manifest,
+    handle_pull, handle_pull_response, handle_request, send_items,
+};
+use session::Session;
 
+mod cancel;
+mod chat;
+mod codec;
 mod hole_puncher;
+mod progress;
+mod prompt;
 mod request;
+mod session;
+
+/// Time allowed for a peer to answer, which includes the receiver deciding whether to accept items.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Items are offered with a manifest first and only sent once the receiver accepts them.
+/// Secrets can also be pulled by their keys from a peer that holds them.
+/// Items for a receiver that is offline are deposited on a mailbox node, which the receiver
+/// collects them from. Chats send each line as a message item. Either side can cancel items
+/// still in flight. Manifests, pulls, deliveries and collections are signed with an id and the
+/// time they were sent, so they can't be replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum Request {
+    Manifest(Stamped<Manifest>),
+    Items(Delivery),
+    Pull(Stamped<Vec<String>>),
+    Deposit(Deposit),
+    /// Carries the ids of the deliveries received from the last collection
+    Collect(Stamped<Vec<Vec<u8>>>),
+    Chat(Stamped<Item>),
+    Cancel(Stamped<Cancel>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Response {
+    Manifest(ManifestResponse),
+    Items(ItemResponse),
+    Pull(PullResponse),
+    Deposit(ManifestResponse),
+    Collect(Collection),
+    Chat(ManifestResponse),
+    Cancel(ManifestResponse),
+}
+
+/// The pulled secrets, sealed and signed like pushed items, and why any were left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullResponse {
+    pub items: Vec<Item>,
+    pub err: Option<String>,
+}
 
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "Event")]
@@ -26,24 +88,25 @@ pub struct Behaviour {
     ping: ping::Behaviour,
     identify: identify::Behaviour,
     dcutr: dcutr::Behaviour,
-    request_response: json::Behaviour<Vec<Item>, ItemResponse>,
+    request_response: request_response::Behaviour<ItemCodec>,
     auto_nat: autonat::Behaviour,
 }
 
+/// Events of the behaviours. Pings and AutoNAT probes are not acted on, so only that they
+/// happened is kept.
 #[derive(Debug)]
-#[allow(clippy::large_enum_variant)]
 pub enum Event {
-    Ping(ping::Event),
+    Ping,
     Identify(identify::Event),
     Relay(relay::client::Event),
     Dcutr(dcutr::Event),
-    RequestResonse(request_response::Event<Vec<Item>, ItemResponse>),
-    AutoNat(autonat::Event),
+    RequestResonse(request_response::Event<Request, Response>),
+    AutoNat,
 }
 
 impl From<ping::Event> for Event {
-    fn from(e: ping::Event) -> Self {
-        Event::Ping(e)
+    fn from(_: ping::Event) -> Self {
+        Event::Ping
     }
 }
 
@@ -65,15 +128,15 @@ impl From<dcutr::Event> for Event {
     }
 }
 
-impl From<request_response::Event<Vec<Item>, ItemResponse>> for Event {
-    fn from(e: request_response::Event<Vec<Item>, ItemResponse>) -> Self {
+impl From<request_response::Event<Request, Response>> for Event {
+    fn from(e: request_response::Event<Request, Response>) -> Self {
         Event::RequestResonse(e)
     }
 }
 
 impl From<autonat::Event> for Event {
-    fn from(v: autonat::Event) -> Self {
-        Self::AutoNat(v)
+    fn from(_: autonat::Event) -> Self {
+        Event::AutoNat
     }
 }
 
@@ -116,15 +179,19 @@ pub fn get_behaviour(
         libp2p::identity::Keypair,
         PeerId,
         Mode,
+        Progress,
+        bool,
     ),
 ) -> Behaviour {
-    let (client, local_key, local_peer_id, mode) = payload;
+    let (client, local_key, local_peer_id, mode, progress, compression) = payload;
+    let mut request_response_config = request_response::Config::default();
+    request_response_config.set_request_timeout(REQUEST_TIMEOUT);
     let autonat_config = match mode {
         Mode::Receive => autonat::Config {
             only_global_ips: false,
             ..Default::default()
         },
-        Mode::Send => autonat::Config {
+        Mode::Send | Mode::Request => autonat::Config {
             retry_interval: Duration::from_secs(10),
             refresh_interval: Duration::from_secs(30),
             boot_delay: Duration::from_secs(5),
@@ -132,7 +199,7 @@ pub fn get_behaviour(
             only_global_ips: false,
             ..Default::default()
         },
-        Mode::List => autonat::Config {
+        _ => autonat::Config {
             ..Default::default()
         },
     };
@@ -145,12 +212,10 @@ pub fn get_behaviour(
             local_key.public(),
         )),
         dcutr: dcutr::Behaviour::new(local_peer_id),
-        request_response: json::Behaviour::<Vec<Item>, ItemResponse>::new(
-            [(
-                StreamProtocol::new("/share-json-protocol"),
-                ProtocolSupport::Full,
-            )],
-            request_response::Config::default(),
+        request_response: request_response::Behaviour::with_codec(
+            ItemCodec::new(progress),
+            codec::protocols(compression),
+            request_response_config,
         ),
         auto_nat: autonat::Behaviour::new(local_peer_id, autonat_config),
     }
@@ -158,28 +223,163 @@ pub fn get_behaviour(
 
 fn request_response_handler(
     swarm: &mut Swarm<Behaviour>,
-    message: Message<Vec<Item>, ItemResponse>,
+    message: Message<Request, Response>,
     peer: PeerId,
+    mode: Mode,
     config: &Config,
+    store: &Store,
+    session: &mut Session,
 ) {
     match message {
         request_response::Message::Request {
             request_id: _,
-            request,
+            request: Request::Manifest(manifest),
+            channel,
+        } => handle_manifest(manifest, config, swarm, channel, peer, store, session),
+        request_response::Message::Request {
+            request_id: _,
+            request: Request::Items(delivery),
             channel,
         } => {
-            info!("Received {} items from {peer}", request.len());
-            handle_request(request, config, swarm, channel);
+            let local_peer_id = *swarm.local_peer_id();
+            let window = config.replay_window();
+            let checked = check_replay(&delivery, peer, local_peer_id, window, store);
+            let (items, no_of_cancelled) = session.drop_cancelled(&peer, delivery.items);
+            let checked = checked.and_then(|_| {
+                if items.is_empty() && no_of_cancelled > 0 || session.take_approved(&peer, &items) {
+                    Ok(())
+                } else {
+                    Err(anyhow!("Items were not accepted by the receiver"))
+                }
+            });
+            session.progress().finish(Direction::Download);
+            if no_of_cancelled > 0 {
+                info!("Dropped {no_of_cancelled} cancelled items from {peer}");
+            }
+            match checked {
+                Ok(_) => {
+                    info!("Received {} items from {peer}", items.len());
+                    let delivery = Delivery { items, ..delivery };
+                    handle_request(
+                        delivery,
+                        no_of_cancelled,
+                        config,
+                        swarm,
+                        channel,
+                        peer,
+                        store,
+                    );
+                }
+                Err(err) => {
+                    error!("Rejected {} items from {peer}: {err}", items.len());
+                    let res = ItemResponse {
+                        status: Status::Failed,
+                        no_of_success: 0,
+                        no_of_fails: items.len(),
+                        no_of_cancelled,
+                        err: Some(err.to_string()),
+                        acks: vec![],
+                        failed: vec![],
+                    };
+                    let sent = swarm
+                        .behaviour_mut()
+                        .request_response
+                        .send_response(channel, Response::Items(res));
+                    if sent.is_err() {
+                        error!("{peer} stopped waiting for the items to be acknowledged");
+                    }
+                }
+            }
+        }
+        request_response::Message::Request {
+            request_id: _,
+            request: Request::Pull(request),
+            channel,
+        } => {
+            info!("{peer} requested {}", request.content.join(", "));
+            handle_pull(request, config, swarm, channel, peer, store, session);
         }
+        request_response::Message::Request {
+            request_id: _,
+            request: Request::Deposit(deposit),
+            channel,
+        } => handle_deposit(deposit, mode, config, swarm, channel, peer, store),
+        request_response::Message::Request {
+            request_id: _,
+            request: Request::Collect(request),
+            channel,
+        } => handle_collect(request, mode, config, swarm, channel, peer, store),
+        request_response::Message::Request {
+            request_id: _,
+            request: Request::Chat(request),
+            channel,
+        } => handle_chat(request, config, swarm, channel, peer, store, session),
+        request_response::Message::Request {
+            request_id: _,
+            request: Request::Cancel(request),
+            channel,
+        } => handle_cancel(request, config, swarm, channel, peer, store, session),
         request_response::Message::Response {
             request_id: _,
-            response,
+            response: Response::Manifest(response),
+        } => match response.accepted {
+            true => {
+                let skipped = session.skip_held(&peer, &response.held);
+                if skipped > 0 {
+                    info!("{peer} already has {skipped} of the files, skipping them");
+                }
+                match session.take_outgoing(&peer) {
+                    Some(items) => send_items(items, swarm, peer, config, session),
+                    None if skipped > 0 => {
+                        session.take_deduplicated(&peer);
+                        info!("{peer} already has every item, nothing was sent");
+                    }
+                    None => info!("Every item for {peer} was cancelled"),
+                }
+            }
+            false => {
+                let reason = response.reason.unwrap_or_default();
+                error!("{peer} did not accept the items: {reason}");
+            }
+        },
+        request_response::Message::Response {
+            request_id: _,
+            response: Response::Items(response),
         } => {
-            info!("Sent {} items successfully", response.no_of_success);
-            if response.no_of_fails > 0 {
-                error!("Failed to save {} items", response.no_of_fails);
+            session.progress().finish(Direction::Upload);
+            handle_item_response(response, swarm, peer, store, session)
+        }
+        request_response::Message::Response {
+            request_id: _,
+            response: Response::Pull(response),
+        } => handle_pull_response(response, config, swarm, peer, store, session),
+        request_response::Message::Response {
+            request_id: _,
+            response: Response::Deposit(response),
+        } => match response.accepted {
+            true => info!("The mailbox {peer} keeps the items until the recipient collects them"),
+            false => {
+                let reason = response.reason.unwrap_or_default();
+                error!("The mailbox {peer} did not take the items: {reason}");
+            }
+        },
+        request_response::Message::Response {
+            request_id: _,
+            response: Response::Collect(collection),
+        } => handle_collection(collection, config, swarm, peer, store, session),
+        request_response::Message::Response {
+            request_id: _,
+            response: Response::Chat(response),
+        } => {
+            if !response.accepted {
+                let reason = response.reason.unwrap_or_default();
+                error!("{peer} did not get the message: {reason}");
             }
         }
+        request_response::Message::Response {
+            request_id: _,
+            response: Response::Cancel(response),
+        } => handle_cancel_response(response, peer, session),
     }
 }
 
@@ -189,7 +389,7 @@ mod tests {
 
     use crate::Mode;
 
-    use super::{get_behaviour, ConnectionDetails};
+    use super::{get_behaviour, progress::Progress, ConnectionDetails};
 
     #[test]
     fn new_connection_details() {
@@ -217,7 +417,14 @@ mod tests {
         let local_key = generate_ed25519();
         let peer_id = PeerId::random();
         let (_, relay_client) = relay::client::new(peer_id);
-        let behaviour = get_behaviour((relay_client, local_key, peer_id, Mode::Send));
+        let behaviour = get_behaviour((
+            relay_client,
+            local_key,
+            peer_id,
+            Mode::Send,
+            Progress::default(),
+            true,
+        ));
         assert!(!behaviour.request_response.is_connected(&peer_id));
     }
 
@@ -226,7 +433,14 @@ mod tests {
         let local_key = generate_ed25519();
         let peer_id = PeerId::random();
         let (_, relay_client) = relay::client::new(peer_id);
-        let behaviour = get_behaviour((relay_client, local_key, peer_id, Mode::Send));
+        let behaviour = get_behaviour((
+            relay_client,
+            local_key,
+            peer_id,
+            Mode::Send,
+            Progress::default(),
+            true,
+        ));
         let nat_status = behaviour.auto_nat.nat_status();
         assert!(!nat_status.is_public());
     }
@@ -0,0 +1,384 @@
+//Reports how far transfers have got. The codec counts the payload bytes of a delivery as it is
+//written or read, and the event loop tells which items they belong to. Progress is drawn as a bar per file
+//when stdout is a terminal, and logged every few seconds otherwise. Deliveries collected from a
+//mailbox come without a manifest, so only the bytes received so far are shown for them.
+
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use libp2p::PeerId;
+use tracing::info;
+
+use crate::item::{format_size, ItemType, Manifest, ManifestEntry};
+
+const LOG_INTERVAL: Duration = Duration::from_secs(2);
+const BAR_TEMPLATE: &str = "{msg:24!} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}";
+const SPINNER_TEMPLATE: &str = "{msg:24!} {spinner} {bytes} {bytes_per_sec}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+#[derive(Debug)]
+enum Event {
+    Started {
+        direction: Direction,
+        peer: PeerId,
+        entries: Option<Vec<ManifestEntry>>,
+    },
+    Advanced {
+        direction: Direction,
+        bytes: u64,
+    },
+    Finished {
+        direction: Direction,
+    },
+    Cancelled {
+        direction: Direction,
+    },
+}
+
+/// A handle to report progress with. The default one reports nothing.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    sender: Option<Sender<Event>>,
+}
+
+impl Progress {
+    /// Starts drawing progress on its own thread.
+    pub fn new() -> Progress {
+        let (sender, receiver) = mpsc::channel();
+        let tty = io::stdout().is_terminal();
+        thread::spawn(move || render(receiver, tty));
+        Progress {
+            sender: Some(sender),
+        }
+    }
+
+    pub fn start(&self, direction: Direction, peer: PeerId, manifest: &Manifest) {
+        self.send(Event::Started {
+            direction,
+            peer,
+            entries: Some(manifest.entries.clone()),
+        });
+    }
+
+    /// Starts a transfer whose items are not known until it is done.
+    pub fn start_without_size(&self, direction: Direction, peer: PeerId) {
+        self.send(Event::Started {
+            direction,
+            peer,
+            entries: None,
+        });
+    }
+
+    /// `bytes` is the number of payload bytes of the current delivery written or read so far.
+    pub fn advance(&self, direction: Direction, bytes: u64) {
+        self.send(Event::Advanced { direction, bytes });
+    }
+
+    pub fn finish(&self, direction: Direction) {
+        self.send(Event::Finished { direction });
+    }
+
+    pub fn cancel(&self, direction: Direction) {
+        self.send(Event::Cancelled { direction });
+    }
+
+    fn send(&self, event: Event) {
+        if let Some(sender) = &self.sender {
+            //the renderer only stops when every handle is dropped
+            let _ = sender.send(event);
+        }
+    }
+}
+
+fn render(receiver: Receiver<Event>, tty: bool) {
+    let multi = MultiProgress::new();
+    let mut transfers: HashMap<Direction, Transfer> = HashMap::new();
+
+    loop {
+        match receiver.recv_timeout(LOG_INTERVAL) {
+            Ok(Event::Started {
+                direction,
+                peer,
+                entries,
+            }) => {
+                let bars = tty.then_some(&multi);
+                let transfer = match entries {
+                    Some(entries) => Transfer::new(direction, peer, entries, bars),
+                    None => Transfer::without_size(direction, peer, bars),
+                };
+                transfers.insert(direction, transfer);
+            }
+            Ok(Event::Advanced { direction, bytes }) => {
+                if let Some(transfer) = transfers.get_mut(&direction) {
+                    transfer.advance(bytes);
+                }
+            }
+            Ok(Event::Finished { direction }) => {
+                if let Some(transfer) = transfers.remove(&direction) {
+                    transfer.finish();
+                }
+            }
+            Ok(Event::Cancelled { direction }) => {
+                if let Some(transfer) = transfers.remove(&direction) {
+                    transfer.cancel();
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if !tty {
+            for transfer in transfers.values_mut() {
+                transfer.log();
+            }
+        }
+    }
+}
+
+struct Transfer {
+    label: String,
+    entries: Vec<ManifestEntry>,
+    bars: Vec<Option<ProgressBar>>,
+    /// Drawn instead of the bars when the size of the transfer is not known
+    spinner: Option<ProgressBar>,
+    /// None until the transfer is done, if it was not known when it started
+    total: Option<u64>,
+    done: u64,
+    started: Instant,
+    logged: (Instant, u64),
+}
+
+impl Transfer {
+    fn new(
+        direction: Direction,
+        peer: PeerId,
+        entries: Vec<ManifestEntry>,
+        multi: Option<&MultiProgress>,
+    ) -> Transfer {
+        let style = ProgressStyle::with_template(BAR_TEMPLATE)
+            .expect("template is valid")
+            .progress_chars("=> ");
+        let bars = entries
+            .iter()
+            .map(|entry| match (multi, &entry.item_type) {
+                (Some(multi), ItemType::File) => {
+                    let bar = multi.add(ProgressBar::new(entry.size));
+                    bar.set_style(style.clone());
+                    bar.set_message(entry.name.clone());
+                    Some(bar)
+                }
+                _ => None,
+            })
+            .collect();
+        let total = entries.iter().map(|entry| entry.size).sum();
+
+        Transfer {
+            label: label(direction, peer),
+            entries,
+            bars,
+            spinner: None,
+            total: Some(total),
+            done: 0,
+            started: Instant::now(),
+            logged: (Instant::now(), 0),
+        }
+    }
+
+    fn without_size(direction: Direction, peer: PeerId, multi: Option<&MultiProgress>) -> Transfer {
+        let spinner = multi.map(|multi| {
+            let spinner = multi.add(ProgressBar::new_spinner());
+            spinner.set_style(
+                ProgressStyle::with_template(SPINNER_TEMPLATE).expect("template is valid"),
+            );
+            spinner.set_message(label(direction, peer));
+            spinner
+        });
+
+        Transfer {
+            label: label(direction, peer),
+            entries: vec![],
+            bars: vec![],
+            spinner,
+            total: None,
+            done: 0,
+            started: Instant::now(),
+            logged: (Instant::now(), 0),
+        }
+    }
+
+    /// Items are encoded one after the other, so the bytes done so far are spread over them in order.
+    fn advance(&mut self, bytes: u64) {
+        let total = match self.total {
+            Some(total) => total,
+            None => {
+                self.done = bytes;
+                if let Some(spinner) = &self.spinner {
+                    spinner.set_position(bytes);
+                }
+                return;
+            }
+        };
+        self.done = bytes.min(total);
+        let mut before = 0;
+        for (entry, bar) in self.entries.iter().zip(&self.bars) {
+            if let Some(bar) = bar {
+                bar.set_position(self.done.saturating_sub(before).min(entry.size));
+            }
+            before += entry.size;
+        }
+    }
+
+    fn log(&mut self) {
+        let (logged_at, logged_done) = self.logged;
+        if self.done == logged_done || logged_at.elapsed() < LOG_INTERVAL {
+            return;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = (self.done as f64 / elapsed) as u64;
+        match self.total {
+            Some(total) => {
+                let eta = match rate {
+                    0 => "unknown".to_string(),
+                    _ => format!("{}s", (total - self.done) / rate),
+                };
+                info!(
+                    "{}: {} of {} ({}%), {}/s, ETA {eta}",
+                    self.label,
+                    format_size(self.done),
+                    format_size(total),
+                    self.done * 100 / total.max(1),
+                    format_size(rate)
+                );
+            }
+            None => info!(
+                "{}: {} so far, {}/s",
+                self.label,
+                format_size(self.done),
+                format_size(rate)
+            ),
+        }
+        self.logged = (Instant::now(), self.done);
+    }
+
+    /// Every payload has arrived once the request is done.
+    fn finish(mut self) {
+        if let Some(total) = self.total {
+            self.advance(total);
+        }
+        for bar in self.bars.into_iter().chain([self.spinner]).flatten() {
+            bar.finish();
+        }
+        info!(
+            "{}: {} in {:.1}s",
+            self.label,
+            format_size(self.done),
+            self.started.elapsed().as_secs_f64()
+        );
+    }
+
+    fn cancel(self) {
+        for bar in self.bars.into_iter().chain([self.spinner]).flatten() {
+            bar.abandon();
+        }
+        match self.total {
+            Some(total) => info!(
+                "{}: cancelled after {} of {}",
+                self.label,
+                format_size(self.done),
+                format_size(total)
+            ),
+            None => info!("{}: cancelled after {}", self.label, format_size(self.done)),
+        }
+    }
+}
+
+fn label(direction: Direction, peer: PeerId) -> String {
+    match direction {
+        Direction::Upload => format!("Sending to {peer}"),
+        Direction::Download => format!("Receiving from {peer}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use anyhow::Result;
+    use assert_fs::prelude::FileWriteBin;
+    use indicatif::MultiProgress;
+    use libp2p::PeerId;
+
+    use super::{Direction, Transfer};
+    use crate::item::{Item, ItemType, Manifest};
+
+    #[test]
+    fn bytes_spread_over_files() -> Result<()> {
+        let first = assert_fs::NamedTempFile::new("first.bin")?;
+        first.write_binary(&[0u8; 1000])?;
+        let second = assert_fs::NamedTempFile::new("second.bin")?;
+        second.write_binary(&[0u8; 500])?;
+        let items = vec![
+            Item::new(first.path().to_str().unwrap().to_string(), ItemType::File)?,
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+            Item::new(second.path().to_str().unwrap().to_string(), ItemType::File)?,
+        ];
+        let manifest = Manifest::new(&items);
+
+        let multi = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let mut transfer = Transfer::new(
+            Direction::Upload,
+            PeerId::random(),
+            manifest.entries,
+            Some(&multi),
+        );
+        assert!(transfer.bars[1].is_none());
+
+        transfer.advance(1200);
+        let positions = transfer
+            .bars
+            .iter()
+            .flatten()
+            .map(|bar| bar.position())
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![1000, 197]);
+
+        transfer.advance(u64::MAX);
+        assert_eq!(transfer.done, 1503);
+
+        transfer.logged.0 = Instant::now() - Duration::from_secs(5);
+        transfer.log();
+        assert_eq!(transfer.logged.1, 1503);
+
+        first.close()?;
+        second.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_without_size() {
+        let multi = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let mut transfer =
+            Transfer::without_size(Direction::Download, PeerId::random(), Some(&multi));
+        transfer.advance(4096);
+        assert_eq!(transfer.done, 4096);
+        assert_eq!(
+            transfer.spinner.as_ref().map(|bar| bar.position()),
+            Some(4096)
+        );
+
+        transfer.logged.0 = Instant::now() - Duration::from_secs(5);
+        transfer.log();
+        assert_eq!(transfer.logged.1, 4096);
+    }
+}
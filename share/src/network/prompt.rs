@@ -0,0 +1,116 @@
+//Approvals are asked for on a thread of their own, one at a time, so that the swarm keeps
+//handling events, and its connections stay up, while the owner answers.
+
+use std::{io, sync::mpsc, thread};
+
+use futures::channel::mpsc::{self as channel, UnboundedReceiver};
+use libp2p::{PeerId, Swarm};
+use request_response::ResponseChannel;
+use tracing::error;
+
+use super::{
+    request::{answer_manifest, answer_pull, compresses, mark_held, save_deposit, save_pulled},
+    session::Session,
+    Behaviour, Response,
+};
+use crate::{
+    config::Config,
+    database::Store,
+    handlers::{
+        approval::{answered, confirm},
+        pull::PullAuthorization,
+    },
+    item::{Deposit, Item, Manifest},
+};
+
+/// What is waiting for the owner to answer a question.
+#[derive(Debug)]
+pub enum Pending {
+    Manifest {
+        peer: PeerId,
+        manifest: Manifest,
+        channel: ResponseChannel<Response>,
+    },
+    Pull {
+        peer: PeerId,
+        keys: Vec<String>,
+        authorization: PullAuthorization,
+        channel: ResponseChannel<Response>,
+    },
+    Deposit {
+        sender: PeerId,
+        deposit: Deposit,
+    },
+    Pulled {
+        peer: PeerId,
+        items: Vec<Item>,
+    },
+}
+
+pub type Answer = (Pending, io::Result<bool>);
+
+/// Asks the questions sent to it in turn, giving back each answer with what it is about.
+pub fn prompts() -> (mpsc::Sender<(String, Pending)>, UnboundedReceiver<Answer>) {
+    let (questions, asked) = mpsc::channel::<(String, Pending)>();
+    let (sender, receiver) = channel::unbounded();
+    thread::spawn(move || {
+        for (question, pending) in asked {
+            let answer = confirm(&question);
+            if sender.unbounded_send((pending, answer)).is_err() {
+                break;
+            }
+        }
+    });
+    (questions, receiver)
+}
+
+/// Carries on with what was waiting for the owner's answer.
+pub fn handle_answer(
+    (pending, answer): Answer,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    store: &Store,
+    session: &mut Session,
+) {
+    session.answered();
+    match pending {
+        Pending::Manifest {
+            peer,
+            manifest,
+            channel,
+        } => {
+            let response = mark_held(answered(answer), &manifest, peer, config, store);
+            answer_manifest(response, manifest, swarm, channel, peer, session)
+        }
+        Pending::Pull {
+            peer,
+            keys,
+            authorization,
+            channel,
+        } => {
+            let allowed = authorization.answered(answer);
+            let compress = compresses(config, session, &peer);
+            answer_pull(&keys, allowed, compress, config, swarm, channel, peer);
+        }
+        Pending::Deposit { sender, deposit } => match answered(answer) {
+            response if response.accepted => {
+                let local_peer_id = *swarm.local_peer_id();
+                save_deposit(&deposit, sender, config, local_peer_id, store);
+            }
+            response => error!(
+                "Rejected the delivery from {sender}: {}",
+                response.reason.unwrap_or_default()
+            ),
+        },
+        Pending::Pulled { peer, items } => match answered(answer) {
+            response if response.accepted => {
+                let local_peer_id = *swarm.local_peer_id();
+                save_pulled(&items, config, peer, local_peer_id, store);
+            }
+            response => error!(
+                "Rejected the secrets from {peer}: {}",
+                response.reason.unwrap_or_default()
+            ),
+        },
+    }
+}
@@ -2,72 +2,883 @@ use std::process::exit;
 
 use libp2p::{PeerId, Swarm};
 use request_response::ResponseChannel;
+use serde::Serialize;
 use tracing::{error, info};
 
+use anyhow::{anyhow, Result};
+
 use crate::{
     config::Config,
-    item::{Item, ItemResponse, ItemType, Status},
+    database::{provenance::Provenance, receipt::Receipt, Store},
+    handlers::{
+        approval::{approve, Approval},
+        dedupe::held_entries,
+        limits::check_limits,
+        mailbox::{collect_deposits, keep_deposit},
+        pull::{authorize_pull, find_secrets},
+        replay::{check_replay, check_stamp},
+    },
+    item::{
+        import_secrets, Acknowledgement, Collection, Delivery, Deposit, Failure, Item,
+        ItemResponse, ItemType, Manifest, ManifestResponse, Stamped, Status,
+    },
     Mode,
 };
 
-use super::Behaviour;
+use super::{
+    codec::COLLECTION_SIZE_MAXIMUM, progress::Direction, prompt::Pending, session::Session,
+    Behaviour, PullResponse, Request, Response,
+};
+
+/// Answers the items `peer` offers in `manifest`, or asks the owner first when the accept policy
+/// says to.
+pub fn handle_manifest(
+    request: Stamped<Manifest>,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    store: &Store,
+    session: &mut Session,
+) {
+    let local_peer_id = *swarm.local_peer_id();
+    let checked = check_stamp(&request, peer, local_peer_id, config, store);
+    let manifest = request.content;
+    let checked =
+        checked.and_then(|_| check_limits(&manifest, session.messages_from(&peer), config));
+    let response = match checked {
+        Ok(_) => match approve(&manifest, peer, config, store) {
+            Approval::Decided(response) => response,
+            Approval::Ask(question) => {
+                let pending = Pending::Manifest {
+                    peer,
+                    manifest,
+                    channel,
+                };
+                session.ask(question, pending);
+                return;
+            }
+        },
+        Err(err) => {
+            error!("Declined {manifest} from {peer}: {err}");
+            ManifestResponse::declined(&err.to_string())
+        }
+    };
+    let response = mark_held(response, &manifest, peer, config, store);
+    answer_manifest(response, manifest, swarm, channel, peer, session)
+}
+
+/// Adds the files in `manifest` that are held already to `response`, if it accepts them.
+pub fn mark_held(
+    mut response: ManifestResponse,
+    manifest: &Manifest,
+    peer: PeerId,
+    config: &Config,
+    store: &Store,
+) -> ManifestResponse {
+    if response.accepted {
+        response.held = held_entries(manifest, peer, config, store);
+        if !response.held.is_empty() {
+            info!(
+                "Already have {} of the files from {peer}, skipping them",
+                response.held.len()
+            );
+        }
+    }
+    response
+}
+
+/// Tells `peer` whether its items are accepted, and which files are held already, then waits for
+/// the rest of them.
+pub fn answer_manifest(
+    response: ManifestResponse,
+    manifest: Manifest,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    session: &mut Session,
+) {
+    let accepted = response.accepted;
+    let manifest = manifest.without(&response.held);
+
+    let sent = swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, Response::Manifest(response));
+    if sent.is_err() {
+        error!("{peer} stopped waiting for an answer to its items");
+        return;
+    }
+    if accepted && !manifest.entries.is_empty() {
+        session
+            .progress()
+            .start(Direction::Download, peer, &manifest);
+        session.approve(peer, manifest);
+    }
+}
 
 pub fn handle_request(
-    request: Vec<Item>,
+    delivery: Delivery,
+    no_of_cancelled: usize,
     config: &Config,
     swarm: &mut Swarm<Behaviour>,
-    channel: ResponseChannel<ItemResponse>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    store: &Store,
 ) {
     let mut items_saved_successfully: Vec<&Item> = vec![];
     let mut items_saved_fail: Vec<&Item> = vec![];
+    let mut failed = vec![];
+    let local_peer_id = *swarm.local_peer_id();
 
-    request.iter().for_each(|item| match item.save(config) {
-        Ok(_) => {
-            info!("Saved {:?} successfully", item.item_type(),);
-            items_saved_successfully.push(item)
-        }
-        Err(err) => {
-            error!("Failed to send {:?}: {}", item.item_type(), err.to_string());
-            items_saved_fail.push(item);
+    let session = Some(delivery.id.as_slice());
+    delivery.items.iter().for_each(|item| {
+        match save_item(item, session, config, peer, local_peer_id, store) {
+            Ok(_) => {
+                info!("Saved {:?} successfully", item.item_type(),);
+                items_saved_successfully.push(item)
+            }
+            Err(err) => {
+                error!("Failed to send {:?}: {}", item.item_type(), err.to_string());
+                failed.push(Failure {
+                    digest: item.digest(),
+                    reason: format!("Failed to save: {err}"),
+                });
+                items_saved_fail.push(item);
+            }
         }
     });
 
     let status = Status::Succes;
 
+    let keypair = config.keypair();
+    let mut acks = vec![];
+    for item in &items_saved_successfully {
+        match Acknowledgement::sign(&keypair, &peer, &item.digest()) {
+            Ok(ack) => acks.push(ack),
+            Err(err) => {
+                error!("Failed to acknowledge {:?}: {err}", item.item_type());
+                failed.push(Failure {
+                    digest: item.digest(),
+                    reason: format!("Saved, but failed to acknowledge: {err}"),
+                });
+            }
+        }
+    }
+
     let res = ItemResponse {
         status,
         no_of_fails: items_saved_fail.len(),
         no_of_success: items_saved_successfully.len(),
+        no_of_cancelled,
         err: None,
+        acks,
+        failed,
     };
 
-    swarm
+    let sent = swarm
         .behaviour_mut()
         .request_response
-        .send_response(channel, res)
-        .unwrap();
+        .send_response(channel, Response::Items(res));
+    if sent.is_err() {
+        error!("{peer} stopped waiting for the items to be acknowledged");
+    }
 }
 
-pub fn make_request(mode: Mode, swarm: &mut Swarm<Behaviour>, peer_id: PeerId, config: &Config) {
-    match mode {
-        Mode::Send => {
-            let items = get_items_to_be_sent(config);
-
-            info!("Sending {} items", items.len());
+/// Sends the items the receiver accepted and does not have yet.
+pub fn send_items(
+    items: Vec<Item>,
+    swarm: &mut Swarm<Behaviour>,
+    peer: PeerId,
+    config: &Config,
+    session: &mut Session,
+) {
+    match Delivery::new(items, &config.keypair(), &peer) {
+        Ok(delivery) => {
+            info!("Sending {} items", delivery.items.len());
+            let manifest = Manifest::new(&delivery.items);
+            session.progress().start(Direction::Upload, peer, &manifest);
             swarm
                 .behaviour_mut()
                 .request_response
-                .send_request(&peer_id, items);
+                .send_request(&peer, Request::Items(delivery));
+        }
+        Err(err) => error!("Failed to sign the items for {peer}: {err}"),
+    }
+}
+
+/// Reports how a delivery went and keeps a receipt for every item the receiver acknowledged.
+pub fn handle_item_response(
+    response: ItemResponse,
+    swarm: &mut Swarm<Behaviour>,
+    peer: PeerId,
+    store: &Store,
+    session: &mut Session,
+) {
+    info!("Sent {} items successfully", response.no_of_success);
+    let deduplicated = session.take_deduplicated(&peer);
+    if deduplicated > 0 {
+        info!("Skipped {deduplicated} files {peer} already had");
+    }
+    if response.no_of_fails > 0 {
+        error!("Failed to save {} items", response.no_of_fails);
+    }
+    if response.no_of_cancelled > 0 {
+        info!("{} items were cancelled", response.no_of_cancelled);
+    }
+    if let Some(err) = &response.err {
+        error!("{err}");
+    }
+
+    let local_peer_id = *swarm.local_peer_id();
+    let sent = match session.take_sent(&peer) {
+        Some(sent) => sent,
+        None => return,
+    };
+    for failure in &response.failed {
+        match sent
+            .entries
+            .iter()
+            .find(|entry| entry.digest == failure.digest)
+        {
+            Some(entry) => error!(
+                "{peer} did not take {:?} {}: {}",
+                entry.item_type, entry.name, failure.reason
+            ),
+            None => error!("{peer} reported an item that was not sent to it"),
+        }
+    }
+    for ack in &response.acks {
+        let entry = match sent.entries.iter().find(|entry| entry.digest == ack.digest) {
+            Some(entry) => entry,
+            None => {
+                error!("{peer} acknowledged an item that was not sent to it");
+                continue;
+            }
+        };
+        let receipt = ack
+            .verify(&peer, &local_peer_id)
+            .and_then(|_| Receipt::new(entry, local_peer_id, peer, ack).save(store));
+        if let Err(err) = receipt {
+            error!(
+                "Failed to keep a receipt for {:?} {}: {err}",
+                entry.item_type, entry.name
+            );
+        }
+    }
+}
+
+/// Saves an item only if it was sealed and signed by the connected peer, and records who sent it
+/// and in which delivery.
+fn save_item(
+    item: &Item,
+    session: Option<&[u8]>,
+    config: &Config,
+    sender: PeerId,
+    local_peer_id: PeerId,
+    store: &Store,
+) -> Result<()> {
+    if !item.is_sealed() {
+        return Err(anyhow!(
+            "{:?} was sent without being sealed",
+            item.item_type()
+        ));
+    }
+    let signature = item.verify(&sender, &local_peer_id)?;
+    let mut saved = item.clone();
+    saved.set_sender(&sender);
+    let saved = saved.save(config)?;
+    if let Some(saved) = &saved {
+        Provenance::displace(saved, store)?;
+    }
+    let saved_as = saved.map(|saved| saved.name);
+    Provenance::new(item, sender, signature, session, saved_as).save(store)
+}
+
+/// Answers a request from `peer` for the secrets named `keys`, asking the owner first about the
+/// ones the config does not allow.
+pub fn handle_pull(
+    request: Stamped<Vec<String>>,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    store: &Store,
+    session: &mut Session,
+) {
+    let local_peer_id = *swarm.local_peer_id();
+    if let Err(err) = check_stamp(&request, peer, local_peer_id, config, store) {
+        error!("Refused the request from {peer}: {err}");
+        let res = PullResponse {
+            items: vec![],
+            err: Some(format!("The request was refused: {err}")),
+        };
+        return send_pulled(res, swarm, channel, peer);
+    }
+    let keys = request.content;
+    let authorization = authorize_pull(&keys, peer, config, store);
+    if authorization.asked.is_empty() {
+        let compress = compresses(config, session, &peer);
+        return answer_pull(
+            &keys,
+            authorization.allowed,
+            compress,
+            config,
+            swarm,
+            channel,
+            peer,
+        );
+    }
+    let question = authorization.question();
+    let pending = Pending::Pull {
+        peer,
+        keys,
+        authorization,
+        channel,
+    };
+    session.ask(question, pending);
+}
+
+/// Sends `peer` the secrets it may pull out of the ones named `keys`.
+pub fn answer_pull(
+    keys: &[String],
+    allowed: Vec<String>,
+    compress: bool,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+) {
+    let res = match pull_items(keys, allowed, compress, config, peer) {
+        Ok(res) => res,
+        Err(err) => {
+            error!("Failed to answer the request from {peer}: {err}");
+            PullResponse {
+                items: vec![],
+                err: Some(format!("The secrets could not be sent: {err}")),
+            }
+        }
+    };
+    info!("Sending {} secrets to {peer}", res.items.len());
+    send_pulled(res, swarm, channel, peer)
+}
+
+fn send_pulled(
+    res: PullResponse,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+) {
+    let sent = swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, Response::Pull(res));
+    if sent.is_err() {
+        error!("{peer} stopped waiting for the secrets it requested");
+    }
+}
+
+fn pull_items(
+    keys: &[String],
+    allowed: Vec<String>,
+    compress: bool,
+    config: &Config,
+    peer: PeerId,
+) -> Result<PullResponse> {
+    let denied = keys
+        .iter()
+        .filter(|key| !allowed.contains(key))
+        .cloned()
+        .collect::<Vec<_>>();
+    let (secrets, missing) = find_secrets(&allowed, config)?;
+
+    let keypair = config.keypair();
+    let mut items = vec![];
+    for secret in secrets {
+        let mut item = Item::from(secret);
+        item.seal(&peer, compress)?;
+        item.sign(&keypair, &peer)?;
+        items.push(item);
+    }
+
+    let mut errors = vec![];
+    if !denied.is_empty() {
+        errors.push(format!("Not allowed to pull {}", denied.join(", ")));
+    }
+    if !missing.is_empty() {
+        errors.push(format!("No secrets named {}", missing.join(", ")));
+    }
+    Ok(PullResponse {
+        items,
+        err: (!errors.is_empty()).then(|| errors.join(". ")),
+    })
+}
+
+/// Saves the secrets pulled from `peer` that were requested from it, once they pass the checks
+/// of pushed items.
+pub fn handle_pull_response(
+    response: PullResponse,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    peer: PeerId,
+    store: &Store,
+    session: &mut Session,
+) {
+    if let Some(err) = &response.err {
+        error!("{peer}: {err}");
+    }
+    let requested = session.take_requested(&peer);
+    let (items, unrequested) = requested_secrets(response.items, &requested);
+    if unrequested > 0 {
+        error!("Dropped {unrequested} items from {peer} that were not requested");
+    }
+    if items.is_empty() {
+        return;
+    }
+
+    let manifest = Manifest::new(&items);
+    if let Err(err) = check_limits(&manifest, session.messages_from(&peer), config) {
+        error!("Rejected the secrets from {peer}: {err}");
+        return;
+    }
+    match approve(&manifest, peer, config, store) {
+        Approval::Decided(response) if response.accepted => {
+            save_pulled(&items, config, peer, *swarm.local_peer_id(), store)
+        }
+        Approval::Decided(response) => error!(
+            "Rejected the secrets from {peer}: {}",
+            response.reason.unwrap_or_default()
+        ),
+        Approval::Ask(question) => session.ask(question, Pending::Pulled { peer, items }),
+    }
+}
+
+/// Keeps the secrets named by the keys requested, one for each key, returning them and how many
+/// other items were dropped.
+fn requested_secrets(items: Vec<Item>, requested: &[String]) -> (Vec<Item>, usize) {
+    let count = items.len();
+    let mut remaining = requested.to_vec();
+    let items = items
+        .into_iter()
+        .filter(|item| {
+            let position = remaining
+                .iter()
+                .position(|key| item.item_type() == ItemType::Secret && key == &item.name());
+            position
+                .map(|position| remaining.remove(position))
+                .is_some()
+        })
+        .collect::<Vec<_>>();
+    let dropped = count - items.len();
+    (items, dropped)
+}
+
+/// Saves the secrets pulled from `peer` once they are approved.
+pub fn save_pulled(
+    items: &[Item],
+    config: &Config,
+    peer: PeerId,
+    local_peer_id: PeerId,
+    store: &Store,
+) {
+    for item in items {
+        match save_item(item, None, config, peer, local_peer_id, store) {
+            Ok(_) => info!("Received {} from {peer}", item.name()),
+            Err(err) => error!("Failed to save {}: {err}", item.name()),
+        }
+    }
+}
+
+/// Keeps a delivery for a recipient that is offline, when running as a mailbox.
+pub fn handle_deposit(
+    deposit: Deposit,
+    mode: Mode,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    store: &Store,
+) {
+    let recipient = deposit.recipient.clone();
+    let kept = match mode {
+        Mode::Mailbox => keep_deposit(deposit, peer, config, store),
+        _ => Err(anyhow!("{} is not a mailbox", swarm.local_peer_id())),
+    };
+    let res = match kept {
+        Ok(_) => {
+            info!("Keeping a delivery from {peer} for {recipient}");
+            ManifestResponse::accepted()
+        }
+        Err(err) => {
+            error!("Refused a delivery from {peer} for {recipient}: {err}");
+            ManifestResponse::declined(&err.to_string())
         }
-        Mode::Receive | Mode::List => {
+    };
+
+    let sent = swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, Response::Deposit(res));
+    if sent.is_err() {
+        error!("{peer} stopped waiting for an answer to its delivery for {recipient}");
+    }
+}
+
+/// Hands over the deliveries kept for `peer`, when running as a mailbox, after deleting the ones
+/// it acknowledged receiving. A collection holds as many as fit in a response.
+pub fn handle_collect(
+    request: Stamped<Vec<Vec<u8>>>,
+    mode: Mode,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    channel: ResponseChannel<Response>,
+    peer: PeerId,
+    store: &Store,
+) {
+    let local_peer_id = *swarm.local_peer_id();
+    let collected = match mode {
+        Mode::Mailbox => check_stamp(&request, peer, local_peer_id, config, store).and_then(|_| {
+            let limit = COLLECTION_SIZE_MAXIMUM;
+            collect_deposits(peer, &request.content, limit, config, store)
+        }),
+        _ => Err(anyhow!("{} is not a mailbox", swarm.local_peer_id())),
+    };
+    let res = match collected {
+        Ok(deposits) => {
+            info!("Handing over {} deliveries to {peer}", deposits.len());
+            Collection {
+                deposits,
+                err: None,
+            }
+        }
+        Err(err) => {
+            error!("Failed to hand over the deliveries for {peer}: {err}");
+            Collection {
+                deposits: vec![],
+                err: Some(err.to_string()),
+            }
+        }
+    };
+
+    let sent = swarm
+        .behaviour_mut()
+        .request_response
+        .send_response(channel, Response::Collect(res));
+    if sent.is_err() {
+        error!("{peer} stopped waiting for its deliveries, they are kept until it collects again");
+    }
+}
+
+/// Asks the mailbox `peer` for the deliveries it keeps for us, acknowledging the ones received
+/// from the last collection so it deletes them.
+fn collect(
+    acknowledged: Vec<Vec<u8>>,
+    swarm: &mut Swarm<Behaviour>,
+    peer: PeerId,
+    config: &Config,
+    session: &Session,
+) {
+    if let Some(request) = stamp(acknowledged, config, &peer) {
+        session
+            .progress()
+            .start_without_size(Direction::Download, peer);
+        swarm
+            .behaviour_mut()
+            .request_response
+            .send_request(&peer, Request::Collect(request));
+    }
+}
+
+/// Saves the deliveries collected from the mailbox `peer` once they pass the checks
+/// of direct deliveries, and collects again until none are left.
+pub fn handle_collection(
+    collection: Collection,
+    config: &Config,
+    swarm: &mut Swarm<Behaviour>,
+    peer: PeerId,
+    store: &Store,
+    session: &mut Session,
+) {
+    session.progress().finish(Direction::Download);
+    if let Some(err) = collection.err {
+        error!("{peer}: {err}");
+        return;
+    }
+    info!(
+        "Collected {} deliveries from the mailbox {peer}",
+        collection.deposits.len()
+    );
+
+    if collection.deposits.is_empty() {
+        return;
+    }
+
+    let local_peer_id = *swarm.local_peer_id();
+    for deposit in &collection.deposits {
+        if let Err(err) = open_deposit(deposit, config, local_peer_id, store, session) {
+            error!("Rejected the delivery from {}: {err}", deposit.sender);
+        }
+    }
+    let acknowledged = collection
+        .deposits
+        .into_iter()
+        .map(|deposit| deposit.delivery.id)
+        .collect();
+    collect(acknowledged, swarm, peer, config, session);
+}
+
+fn open_deposit(
+    deposit: &Deposit,
+    config: &Config,
+    local_peer_id: PeerId,
+    store: &Store,
+    session: &mut Session,
+) -> Result<()> {
+    let sender = deposit.sender()?;
+    let window = config.mailbox_window();
+    check_replay(&deposit.delivery, sender, local_peer_id, window, store)?;
+    let manifest = deposit.manifest();
+    check_limits(&manifest, session.messages_from(&sender), config)?;
+    match approve(&manifest, sender, config, store) {
+        Approval::Decided(response) if response.accepted => {
+            save_deposit(deposit, sender, config, local_peer_id, store)
+        }
+        Approval::Decided(response) => {
+            return Err(anyhow!("{}", response.reason.unwrap_or_default()))
+        }
+        Approval::Ask(question) => {
+            let pending = Pending::Deposit {
+                sender,
+                deposit: deposit.clone(),
+            };
+            session.ask(question, pending);
+        }
+    }
+    Ok(())
+}
+
+/// Saves the items of a delivery collected from a mailbox once it is approved.
+pub fn save_deposit(
+    deposit: &Deposit,
+    sender: PeerId,
+    config: &Config,
+    local_peer_id: PeerId,
+    store: &Store,
+) {
+    let session = Some(deposit.delivery.id.as_slice());
+    for item in &deposit.delivery.items {
+        match save_item(item, session, config, sender, local_peer_id, store) {
+            Ok(_) => info!("Saved {:?} successfully", item.item_type()),
+            Err(err) => error!("Failed to save {:?}: {err}", item.item_type()),
+        }
+    }
+}
+
+pub fn make_request(
+    mode: Mode,
+    swarm: &mut Swarm<Behaviour>,
+    peer_id: PeerId,
+    remote_peer_id: Option<PeerId>,
+    config: &Config,
+    session: &mut Session,
+) {
+    match mode {
+        Mode::Send if session.is_mailbox(&peer_id) && !session.is_offered(&peer_id) => {
+            let recipient = remote_peer_id.expect("a recipient is required to send items");
+            //the mailbox can't tell whether the recipient takes compressed items
+            let items = sealed_items(config, &recipient, false);
+            session.mark_offered(peer_id);
+
+            let local_peer_id = *swarm.local_peer_id();
+            match Delivery::new(items, &config.keypair(), &recipient) {
+                Ok(delivery) => {
+                    let deposit = Deposit::new(local_peer_id, recipient, delivery);
+                    info!(
+                        "Leaving {} for {recipient} with the mailbox {peer_id}",
+                        deposit.manifest()
+                    );
+                    swarm
+                        .behaviour_mut()
+                        .request_response
+                        .send_request(&peer_id, Request::Deposit(deposit));
+                }
+                Err(err) => {
+                    error!("Failed to sign the items for {recipient}: {err}");
+                    exit(1);
+                }
+            }
+        }
+        Mode::Send if !session.is_offered(&peer_id) && session.is_identified(&peer_id) => {
+            if config.interactive() && !has_items(config) {
+                session.mark_offered(peer_id);
+            } else {
+                let items = sealed_items(config, &peer_id, compresses(config, session, &peer_id));
+                let manifest = session.offer(peer_id, items);
+                info!("Offering {manifest} to {peer_id}");
+                if let Some(request) = stamp(manifest, config, &peer_id) {
+                    swarm
+                        .behaviour_mut()
+                        .request_response
+                        .send_request(&peer_id, Request::Manifest(request));
+                }
+            }
+            if config.interactive() {
+                info!("Type `secret KEY=VALUE`, `file PATH` or `message TEXT` to send more items, or Ctrl-D to finish");
+            }
+        }
+        Mode::Request if !session.is_offered(&peer_id) => {
+            let keys = match config.keys() {
+                Some(keys) if !keys.is_empty() => keys,
+                _ => {
+                    error!("Pass in the keys of the secrets to request with the `-s` flag");
+                    exit(1);
+                }
+            };
+            session.request(peer_id, keys.clone());
+
+            info!("Requesting {} from {peer_id}", keys.join(", "));
+            if let Some(request) = stamp(keys, config, &peer_id) {
+                swarm
+                    .behaviour_mut()
+                    .request_response
+                    .send_request(&peer_id, Request::Pull(request));
+            }
+        }
+        Mode::Chat if !session.is_offered(&peer_id) && session.join_chat(peer_id) => {
+            session.mark_offered(peer_id);
+            info!("Chatting with {peer_id}. Type a message and press Enter to send it, or Ctrl-D to leave");
+        }
+        Mode::Receive if session.is_mailbox(&peer_id) && !session.is_offered(&peer_id) => {
+            session.mark_offered(peer_id);
+            info!("Collecting the items left for you with the mailbox {peer_id}");
+            collect(vec![], swarm, peer_id, config, session);
+        }
+        _ => {
             // if !is_ip_whitelisted(event, config)
         }
     }
 }
 
+/// Offers the item typed at the prompt of an interactive `send` to `recipient`.
+pub fn send_typed(
+    line: String,
+    recipient: PeerId,
+    swarm: &mut Swarm<Behaviour>,
+    config: &Config,
+    session: &mut Session,
+) {
+    if !session.is_offered(&recipient) {
+        error!("Not connected to {recipient} yet");
+        return;
+    }
+    if session.is_pending(&recipient) {
+        error!("Wait for the last items to be delivered");
+        return;
+    }
+
+    let mut item = match typed_item(&line) {
+        Ok(Some(item)) => item,
+        Ok(None) => return,
+        Err(err) => {
+            error!("{err}");
+            return;
+        }
+    };
+    let compress = compresses(config, session, &recipient);
+    if let Err(err) = seal_item(&mut item, config, &recipient, compress) {
+        error!(
+            "Failed to seal {:?} for {recipient}: {err}",
+            item.item_type()
+        );
+        return;
+    }
+
+    let manifest = session.offer(recipient, vec![item]);
+    info!("Offering {manifest} to {recipient}");
+    if let Some(request) = stamp(manifest, config, &recipient) {
+        swarm
+            .behaviour_mut()
+            .request_response
+            .send_request(&recipient, Request::Manifest(request));
+    }
+}
+
+/// Signs `content` for `peer` with an id and the time it is sent, to be sent as a request.
+pub fn stamp<T: Serialize>(content: T, config: &Config, peer: &PeerId) -> Option<Stamped<T>> {
+    match Stamped::new(content, &config.keypair(), peer) {
+        Ok(request) => Some(request),
+        Err(err) => {
+            error!("Failed to sign the request to {peer}: {err}");
+            None
+        }
+    }
+}
+
+/// Parses `secret KEY=VALUE`, `file PATH` or `message TEXT`. Blank lines are skipped.
+fn typed_item(line: &str) -> Result<Option<Item>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let (kind, value) = match line.split_once(' ') {
+        Some((kind, value)) => (kind, value.trim()),
+        None => (line, ""),
+    };
+    let item_type = match kind {
+        "secret" | "s" => ItemType::Secret,
+        "file" | "f" => ItemType::File,
+        "message" | "m" => ItemType::Message,
+        _ => {
+            return Err(anyhow!(
+                "Unknown item {kind}. Type `secret KEY=VALUE`, `file PATH` or `message TEXT`"
+            ))
+        }
+    };
+    if value.is_empty() {
+        return Err(anyhow!("Nothing to send after {kind}"));
+    }
+    Item::new(value.to_string(), item_type).map(Some)
+}
+
+/// Whether items sealed for `peer` are compressed: only if compression is on here and `peer`
+/// takes compressed items, which it says by supporting the compressed protocol.
+pub fn compresses(config: &Config, session: &Session, peer: &PeerId) -> bool {
+    config.compression() && session.takes_compressed(peer)
+}
+
+fn seal_item(item: &mut Item, config: &Config, recipient: &PeerId, compress: bool) -> Result<()> {
+    item.set_namespace(&config.namespace())?;
+    item.set_expiry(config.expiry().as_ref());
+    item.seal(recipient, compress)?;
+    item.sign(&config.keypair(), recipient)
+}
+
+/// The items to send, sealed and signed for `recipient`.
+fn sealed_items(config: &Config, recipient: &PeerId, compress: bool) -> Vec<Item> {
+    let mut items = get_items_to_be_sent(config);
+    for item in items.iter_mut() {
+        if let Err(err) = seal_item(item, config, recipient, compress) {
+            error!(
+                "Failed to seal {:?} for {recipient}: {err}",
+                item.item_type()
+            );
+            exit(1);
+        }
+    }
+    items
+}
+
+fn has_items(config: &Config) -> bool {
+    config.file().is_some()
+        || config.secret().is_some()
+        || config.message().is_some()
+        || !config.imports().is_empty()
+}
+
 fn get_items_to_be_sent(opts: &Config) -> Vec<Item> {
-    if opts.file().is_none() && opts.secret().is_none() && opts.message().is_none() {
-        error!("Pass in a secret with the `-s` flag or a message with `-m` flag or a file path with the `f` flag");
+    if !has_items(opts) {
+        error!("Pass in a secret with the `-s` flag or a message with `-m` flag or a file path with the `f` flag or a file of secrets with the `--import` flag");
         exit(1);
     }
 
@@ -75,6 +886,15 @@ fn get_items_to_be_sent(opts: &Config) -> Vec<Item> {
         None => vec![],
         Some(secrets) => secrets.iter().map(Item::from).collect::<Vec<_>>(),
     };
+    for import in opts.imports() {
+        match import_secrets(&import) {
+            Ok(secrets) => items.extend(secrets.iter().map(Item::from)),
+            Err(err) => {
+                error!("{err:#}");
+                exit(1);
+            }
+        }
+    }
 
     let mut messages = {
         match &opts.message() {
@@ -86,11 +906,12 @@ fn get_items_to_be_sent(opts: &Config) -> Vec<Item> {
                 .collect::<Vec<_>>(),
         }
     };
+    let directories = opts.directories();
     let mut files = match &opts.file() {
         None => vec![],
         Some(paths) => paths
             .iter()
-            .map(|path| match Item::new(path.to_string(), ItemType::File) {
+            .flat_map(|path| match Item::files(path, &directories) {
                 Err(err) => {
                     error!("{}", err.to_string());
                     exit(1);
@@ -104,3 +925,44 @@ fn get_items_to_be_sent(opts: &Config) -> Vec<Item> {
     items.append(&mut files);
     items
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{requested_secrets, typed_item};
+    use crate::item::{Item, ItemType};
+
+    #[test]
+    fn typed_items() -> Result<()> {
+        let secret = typed_item("secret foo,bar")?.unwrap();
+        assert_eq!(secret.item_type(), ItemType::Secret);
+        assert_eq!(secret.name(), "foo");
+
+        let message = typed_item("m  hi there ")?.unwrap();
+        assert_eq!(message.payload(), b"hi there");
+
+        assert!(typed_item("   ")?.is_none());
+        assert!(typed_item("message").is_err());
+        assert!(typed_item("secrets foo,bar").is_err());
+        assert!(typed_item("file /does/not/exist").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn only_requested_secrets() -> Result<()> {
+        let items = vec![
+            Item::new("STRIPE_KEY,sk_test".to_string(), ItemType::Secret)?,
+            Item::new("STRIPE_KEY,sk_live".to_string(), ItemType::Secret)?,
+            Item::new("GITHUB_TOKEN,ghp".to_string(), ItemType::Secret)?,
+            Item::new("STRIPE_KEY".to_string(), ItemType::Message)?,
+        ];
+        let requested = vec!["STRIPE_KEY".to_string(), "DATABASE_URL".to_string()];
+
+        let (kept, dropped) = requested_secrets(items.clone(), &requested);
+        assert_eq!(kept, items[..1].to_vec());
+        assert_eq!(dropped, 3);
+        assert_eq!(requested_secrets(items, &[]).0, vec![]);
+        Ok(())
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::{HashMap, HashSet};
+
+use libp2p::PeerId;
+
+use super::{progress::Progress, prompt::Pending};
+use crate::item::{Cancel, Item, ItemType, Manifest, ManifestEntry};
+
+/// Tracks the two-phase exchanges with each peer: items offered with a manifest and waiting
+/// for the receiver's answer, and manifests the receiver accepted and is waiting on.
+/// Either side can cancel items still in flight. Questions for the owner wait here to be asked.
+#[derive(Debug, Default)]
+pub struct Session {
+    offered: HashSet<PeerId>,
+    compressed: HashMap<PeerId, bool>,
+    requested: HashMap<PeerId, Vec<String>>,
+    outgoing: HashMap<PeerId, Vec<Item>>,
+    sent: HashMap<PeerId, Manifest>,
+    approved: HashMap<PeerId, Manifest>,
+    messages: HashMap<PeerId, usize>,
+    cancelled: HashMap<PeerId, Cancel>,
+    cancelling: HashSet<PeerId>,
+    deduplicated: HashMap<PeerId, usize>,
+    closing: bool,
+    mailbox: Option<PeerId>,
+    chatting: bool,
+    chat: Option<PeerId>,
+    progress: Progress,
+    questions: Vec<(String, Pending)>,
+    asking: usize,
+}
+
+impl Session {
+    pub fn new(progress: Progress) -> Session {
+        Session {
+            progress,
+            ..Default::default()
+        }
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// The mailbox node items are left on or collected from.
+    pub fn set_mailbox(&mut self, peer: PeerId) {
+        self.mailbox = Some(peer);
+    }
+
+    pub fn is_mailbox(&self, peer: &PeerId) -> bool {
+        self.mailbox.as_ref() == Some(peer)
+    }
+
+    /// Starts a chat, with `peer` or with the first peer to join.
+    pub fn open_chat(&mut self, peer: Option<PeerId>) {
+        self.chatting = true;
+        self.chat = peer;
+    }
+
+    /// Whether `peer` is the one in the chat, letting it join if nobody has yet.
+    pub fn join_chat(&mut self, peer: PeerId) -> bool {
+        if !self.chatting {
+            return false;
+        }
+        *self.chat.get_or_insert(peer) == peer
+    }
+
+    pub fn chat_peer(&self) -> Option<PeerId> {
+        self.chat
+    }
+
+    /// Records whether `peer` takes compressed items, once it said which protocols it supports.
+    pub fn identify(&mut self, peer: PeerId, compressed: bool) {
+        self.compressed.insert(peer, compressed);
+    }
+
+    pub fn is_identified(&self, peer: &PeerId) -> bool {
+        self.compressed.contains_key(peer)
+    }
+
+    /// Whether `peer` takes compressed items. Peers that have not said so yet don't.
+    pub fn takes_compressed(&self, peer: &PeerId) -> bool {
+        self.compressed.get(peer).copied().unwrap_or(false)
+    }
+
+    /// Whether items were already offered to `peer`, e.g. before the relayed connection was upgraded.
+    pub fn is_offered(&self, peer: &PeerId) -> bool {
+        self.offered.contains(peer)
+    }
+
+    pub fn mark_offered(&mut self, peer: PeerId) {
+        self.offered.insert(peer);
+    }
+
+    /// Keeps the keys of the secrets requested from `peer`, which is only allowed to send those.
+    pub fn request(&mut self, peer: PeerId, keys: Vec<String>) {
+        self.mark_offered(peer);
+        self.requested.insert(peer, keys);
+    }
+
+    pub fn take_requested(&mut self, peer: &PeerId) -> Vec<String> {
+        self.requested.remove(peer).unwrap_or_default()
+    }
+
+    /// Keeps `items` until `peer` answers and returns the manifest to send them with.
+    pub fn offer(&mut self, peer: PeerId, items: Vec<Item>) -> Manifest {
+        let manifest = Manifest::new(&items);
+        self.mark_offered(peer);
+        self.cancelled.remove(&peer);
+        self.outgoing.insert(peer, items);
+        manifest
+    }
+
+    /// Returns the items to send to `peer`, keeping their manifest until the receiver acknowledges them.
+    /// None if there are none left to send.
+    pub fn take_outgoing(&mut self, peer: &PeerId) -> Option<Vec<Item>> {
+        let items = self.outgoing.remove(peer)?;
+        if items.is_empty() {
+            return None;
+        }
+        self.sent.insert(*peer, Manifest::new(&items));
+        Some(items)
+    }
+
+    /// Drops the files offered to `peer` that it already has, returning how many were dropped.
+    pub fn skip_held(&mut self, peer: &PeerId, held: &[Vec<u8>]) -> usize {
+        let items = match self.outgoing.get_mut(peer) {
+            Some(items) => items,
+            None => return 0,
+        };
+        let count = items.len();
+        items.retain(|item| item.content_hash().is_none() || !held.contains(&item.digest()));
+        let skipped = count - items.len();
+        *self.deduplicated.entry(*peer).or_default() += skipped;
+        skipped
+    }
+
+    /// Number of files `peer` already had since this was last asked.
+    pub fn take_deduplicated(&mut self, peer: &PeerId) -> usize {
+        self.deduplicated.remove(peer).unwrap_or(0)
+    }
+
+    /// The items offered to `peer` or accepted from it that have not been delivered yet.
+    pub fn in_flight(&self, peer: &PeerId) -> Option<Manifest> {
+        match self.outgoing.get(peer) {
+            Some(items) => Some(Manifest::new(items)),
+            None => self.sent.get(peer).or(self.approved.get(peer)).cloned(),
+        }
+    }
+
+    /// The peers with items in flight.
+    pub fn active_peers(&self) -> Vec<PeerId> {
+        let mut peers = self
+            .outgoing
+            .keys()
+            .chain(self.sent.keys())
+            .chain(self.approved.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        peers.sort();
+        peers.dedup();
+        peers
+    }
+
+    /// Stops the items of `peer` that `cancel` covers from being sent or saved, and returns
+    /// the manifest entries that were still in flight.
+    pub fn cancel(&mut self, peer: PeerId, cancel: Cancel) -> Vec<ManifestEntry> {
+        let entries = self
+            .in_flight(&peer)
+            .map(|manifest| manifest.entries)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| cancel.covers(&entry.digest))
+            .collect();
+
+        if let Some(items) = self.outgoing.get_mut(&peer) {
+            items.retain(|item| !cancel.covers(&item.digest()));
+        }
+        for manifest in [self.sent.get_mut(&peer), self.approved.get_mut(&peer)]
+            .into_iter()
+            .flatten()
+        {
+            manifest
+                .entries
+                .retain(|entry| !cancel.covers(&entry.digest));
+        }
+        if cancel.is_all() {
+            self.outgoing.remove(&peer);
+            self.sent.remove(&peer);
+            self.approved.remove(&peer);
+        }
+
+        match self.cancelled.get_mut(&peer) {
+            Some(cancelled) => cancelled.merge(cancel),
+            None => {
+                self.cancelled.insert(peer, cancel);
+            }
+        }
+        entries
+    }
+
+    /// Waits for `peer` to acknowledge a cancellation.
+    pub fn await_cancel(&mut self, peer: PeerId) {
+        self.cancelling.insert(peer);
+    }
+
+    pub fn cancel_answered(&mut self, peer: &PeerId) {
+        self.cancelling.remove(peer);
+    }
+
+    /// Closes the session once every cancellation is acknowledged.
+    pub fn close(&mut self) {
+        self.closing = true;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closing && self.cancelling.is_empty()
+    }
+
+    /// Drops the items delivered by `peer` that were cancelled, returning the rest and how many were dropped.
+    pub fn drop_cancelled(&mut self, peer: &PeerId, items: Vec<Item>) -> (Vec<Item>, usize) {
+        let cancel = match self.cancelled.remove(peer) {
+            Some(cancel) => cancel,
+            None => return (items, 0),
+        };
+        let count = items.len();
+        let items = items
+            .into_iter()
+            .filter(|item| !cancel.covers(&item.digest()))
+            .collect::<Vec<_>>();
+        let dropped = count - items.len();
+        (items, dropped)
+    }
+
+    /// Whether items offered to `peer` are still waiting for an answer or to be acknowledged.
+    pub fn is_pending(&self, peer: &PeerId) -> bool {
+        self.outgoing.contains_key(peer) || self.sent.contains_key(peer)
+    }
+
+    pub fn take_sent(&mut self, peer: &PeerId) -> Option<Manifest> {
+        self.sent.remove(peer)
+    }
+
+    pub fn approve(&mut self, peer: PeerId, manifest: Manifest) {
+        self.cancelled.remove(&peer);
+        *self.messages.entry(peer).or_default() += manifest.count(ItemType::Message);
+        self.approved.insert(peer, manifest);
+    }
+
+    /// Number of messages accepted from `peer` so far.
+    pub fn messages_from(&self, peer: &PeerId) -> usize {
+        self.messages.get(peer).copied().unwrap_or(0)
+    }
+
+    /// Keeps `question` to ask the owner, and what waits for the answer.
+    pub fn ask(&mut self, question: String, pending: Pending) {
+        self.questions.push((question, pending));
+        self.asking += 1;
+    }
+
+    /// The questions to ask since this was last called.
+    pub fn take_questions(&mut self) -> Vec<(String, Pending)> {
+        std::mem::take(&mut self.questions)
+    }
+
+    pub fn answered(&mut self) {
+        self.asking = self.asking.saturating_sub(1);
+    }
+
+    /// Whether a question is waiting for the owner to answer it.
+    pub fn is_asking(&self) -> bool {
+        self.asking > 0
+    }
+
+    /// Whether `items` are the ones `peer` got approved. An approval only covers one delivery.
+    pub fn take_approved(&mut self, peer: &PeerId, items: &[Item]) -> bool {
+        self.approved
+            .remove(peer)
+            .map(|manifest| manifest.matches(items))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use libp2p::{identity::Keypair, PeerId};
+
+    use super::Session;
+    use crate::item::{Cancel, Item, ItemType, Manifest};
+
+    #[test]
+    fn offered_items() -> Result<()> {
+        let peer = PeerId::random();
+        let items = vec![Item::new("foo,bar".to_string(), ItemType::Secret)?];
+        let mut session = Session::default();
+        assert!(!session.is_offered(&peer));
+
+        let manifest = session.offer(peer, items.clone());
+        assert!(manifest.matches(&items));
+        assert!(session.is_offered(&peer));
+        assert!(session.is_pending(&peer));
+        assert_eq!(session.take_outgoing(&peer), Some(items));
+        assert_eq!(session.take_outgoing(&peer), None);
+        assert!(session.is_pending(&peer));
+        assert_eq!(session.take_sent(&peer), Some(manifest));
+        assert_eq!(session.take_sent(&peer), None);
+        assert!(!session.is_pending(&peer));
+        Ok(())
+    }
+
+    #[test]
+    fn approved_items() -> Result<()> {
+        let peer = PeerId::random();
+        let items = vec![Item::new("foo,bar".to_string(), ItemType::Secret)?];
+        let mut session = Session::default();
+        assert!(!session.take_approved(&peer, &items));
+
+        session.approve(peer, Manifest::new(&items));
+        let other = vec![Item::new("foo,baz".to_string(), ItemType::Secret)?];
+        assert!(!session.take_approved(&peer, &other));
+
+        session.approve(peer, Manifest::new(&items));
+        assert!(session.take_approved(&peer, &items));
+        assert!(!session.take_approved(&peer, &items));
+        Ok(())
+    }
+
+    #[test]
+    fn accepted_messages() -> Result<()> {
+        let peer = PeerId::random();
+        let items = vec![
+            Item::new("hi".to_string(), ItemType::Message)?,
+            Item::new("there".to_string(), ItemType::Message)?,
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+        ];
+        let mut session = Session::default();
+        session.approve(peer, Manifest::new(&items));
+        session.approve(peer, Manifest::new(&items[..1]));
+        assert_eq!(session.messages_from(&peer), 3);
+        assert_eq!(session.messages_from(&PeerId::random()), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_peers() {
+        let peer = PeerId::random();
+        let mut session = Session::default();
+        assert!(!session.is_identified(&peer));
+        assert!(!session.takes_compressed(&peer));
+
+        session.identify(peer, true);
+        assert!(session.is_identified(&peer));
+        assert!(session.takes_compressed(&peer));
+        session.identify(peer, false);
+        assert!(!session.takes_compressed(&peer));
+    }
+
+    #[test]
+    fn first_peer_joins_chat() {
+        let peer = PeerId::random();
+        let mut session = Session::default();
+        assert!(!session.join_chat(peer));
+
+        session.open_chat(None);
+        assert_eq!(session.chat_peer(), None);
+        assert!(session.join_chat(peer));
+        assert!(session.join_chat(peer));
+        assert!(!session.join_chat(PeerId::random()));
+        assert_eq!(session.chat_peer(), Some(peer));
+    }
+
+    #[test]
+    fn cancelled_items() -> Result<()> {
+        let peer = PeerId::random();
+        let items = vec![
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+            Item::new("baz,woo".to_string(), ItemType::Secret)?,
+        ];
+        let mut session = Session::default();
+        let manifest = session.offer(peer, items.clone());
+        assert_eq!(session.active_peers(), vec![peer]);
+
+        let cancel = Cancel::named(&manifest, "foo", "not needed").unwrap();
+        let cancelled = session.cancel(peer, cancel);
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(session.take_outgoing(&peer), Some(items[1..].to_vec()));
+
+        //items already on their way are dropped when they arrive
+        let (kept, dropped) = session.drop_cancelled(&peer, items.clone());
+        assert_eq!((kept, dropped), (items[1..].to_vec(), 1));
+
+        session.cancel(peer, Cancel::all("closing"));
+        assert!(!session.is_pending(&peer));
+        assert!(session.active_peers().is_empty());
+
+        session.approve(peer, Manifest::new(&items));
+        session.cancel(peer, Cancel::all("closing"));
+        assert!(!session.take_approved(&peer, &items));
+        assert_eq!(session.drop_cancelled(&peer, items).1, 2);
+
+        session.await_cancel(peer);
+        session.close();
+        assert!(!session.is_closed());
+        session.cancel_answered(&peer);
+        assert!(session.is_closed());
+        Ok(())
+    }
+
+    #[test]
+    fn held_files_skipped() -> Result<()> {
+        let peer = Keypair::generate_ed25519().public().to_peer_id();
+        let file = assert_fs::NamedTempFile::new("fixture.json")?;
+        std::fs::write(file.path(), "{}")?;
+        let mut items = vec![
+            Item::new(file.path().to_str().unwrap().to_string(), ItemType::File)?,
+            Item::new("foo,bar".to_string(), ItemType::Secret)?,
+        ];
+        for item in items.iter_mut() {
+            item.seal(&peer, true)?;
+        }
+        let mut session = Session::default();
+        session.offer(peer, items.clone());
+
+        //only files can be skipped
+        let held = vec![items[0].digest(), items[1].digest()];
+        assert_eq!(session.skip_held(&peer, &held), 1);
+        assert_eq!(session.take_outgoing(&peer), Some(items[1..].to_vec()));
+        assert_eq!(session.take_deduplicated(&peer), 1);
+        assert_eq!(session.take_deduplicated(&peer), 0);
+
+        file.close()?;
+        Ok(())
+    }
+}